@@ -0,0 +1,292 @@
+use super::command;
+use super::docker_compose;
+use super::log;
+use anyhow::Context;
+use std::collections;
+
+pub const RULES: [&str; 6] = [
+    CONTAINER_NAME,
+    MUTABLE_TAG,
+    NO_HEALTHCHECK,
+    NO_STOP_GRACE_PERIOD,
+    PUBLISHED_PORT,
+    SINGLE_STOP_FIRST,
+];
+
+const CONTAINER_NAME: &str = "container-name";
+const MUTABLE_TAG: &str = "mutable-tag";
+const NO_HEALTHCHECK: &str = "no-healthcheck";
+const NO_STOP_GRACE_PERIOD: &str = "no-stop-grace-period";
+const PUBLISHED_PORT: &str = "published-port";
+const SINGLE_STOP_FIRST: &str = "single-stop-first";
+
+pub fn go(
+    In {
+        docker_compose_cli,
+        ignored_rules,
+    }: In,
+) -> anyhow::Result<()> {
+    let compose_app_definition = command::stdout_json::<ComposeAppDefinition>(
+        docker_compose_cli
+            .command()
+            .args(["config", "--format", "json"]),
+        command::Stderr::Inherit,
+    )
+    .context("Unable to read Compose configuration")?;
+
+    let problems = get_problems(&compose_app_definition.services)
+        .into_iter()
+        .filter(|problem| !ignored_rules.iter().any(|rule| rule == problem.rule))
+        .collect::<Vec<_>>();
+
+    for Problem {
+        message,
+        rule,
+        service_name,
+    } in &problems
+    {
+        println!("{service_name}: {message} [{rule}]");
+    }
+
+    match problems.len() {
+        0 => {
+            log::info!("No problems found.");
+            Ok(())
+        }
+        1 => Err(anyhow::anyhow!(
+            "Found 1 problem that may break zero-downtime deploys"
+        )),
+        count => Err(anyhow::anyhow!(
+            "Found {count} problems that may break zero-downtime deploys"
+        )),
+    }
+}
+
+pub struct In<'a> {
+    pub docker_compose_cli: docker_compose::Cli<'a>,
+    pub ignored_rules: Vec<String>,
+}
+
+#[derive(Debug, PartialEq)]
+struct Problem {
+    message: String,
+    rule: &'static str,
+    service_name: String,
+}
+
+#[derive(serde::Deserialize)]
+struct ComposeAppDefinition {
+    services: collections::BTreeMap<String, ServiceDefinition>,
+}
+
+#[derive(serde::Deserialize)]
+struct ServiceDefinition {
+    build: Option<serde_json::Value>,
+    container_name: Option<String>,
+    deploy: Option<Deploy>,
+    healthcheck: Option<Healthcheck>,
+    image: Option<String>,
+    #[serde(default)]
+    ports: Vec<docker_compose::Port>,
+    stop_grace_period: Option<String>,
+    #[serde(rename = "x-wheelsticks")]
+    settings: Option<Settings>,
+}
+
+#[derive(serde::Deserialize)]
+struct Deploy {
+    replicas: Option<u16>,
+    update_config: Option<UpdateConfig>,
+}
+
+#[derive(serde::Deserialize)]
+struct UpdateConfig {
+    order: Option<String>,
+}
+
+#[derive(serde::Deserialize)]
+struct Settings {
+    readiness: Option<serde_json::Value>,
+    update_order: Option<String>,
+}
+
+#[derive(serde::Deserialize)]
+struct Healthcheck {
+    #[serde(default)]
+    disable: bool,
+    #[serde(default)]
+    test: Vec<String>,
+}
+
+fn get_problems(services: &collections::BTreeMap<String, ServiceDefinition>) -> Vec<Problem> {
+    services
+        .iter()
+        .flat_map(|(service_name, service_definition)| {
+            check_service(service_definition)
+                .into_iter()
+                .map(|(rule, message)| Problem {
+                    message,
+                    rule,
+                    service_name: service_name.clone(),
+                })
+        })
+        .collect()
+}
+
+fn check_service(service_definition: &ServiceDefinition) -> Vec<(&'static str, String)> {
+    let mut problems = vec![];
+    let deploy = service_definition.deploy.as_ref();
+    let update_order = service_definition
+        .settings
+        .as_ref()
+        .and_then(|settings| settings.update_order.as_deref())
+        .or(deploy
+            .and_then(|deploy| deploy.update_config.as_ref())
+            .and_then(|update_config| update_config.order.as_deref()));
+    let is_start_first = update_order == Some("start-first");
+    let replica_count = deploy.and_then(|deploy| deploy.replicas).unwrap_or(1);
+
+    // A readiness probe of Wheelsticks does as well.
+    let has_healthcheck = service_definition
+        .healthcheck
+        .as_ref()
+        .is_some_and(|healthcheck| {
+            !healthcheck.disable
+                && healthcheck.test.first().map(|test| test.as_str()) != Some("NONE")
+        })
+        || service_definition
+            .settings
+            .as_ref()
+            .is_some_and(|settings| settings.readiness.is_some());
+    if is_start_first && !has_healthcheck {
+        problems.push((
+            NO_HEALTHCHECK,
+            "Start-first service has no healthcheck or readiness probe, so old containers \
+            may be stopped before new ones are ready"
+                .into(),
+        ));
+    }
+
+    if let Some(container_name) = &service_definition.container_name {
+        problems.push((
+            CONTAINER_NAME,
+            format!("Fixed container name {container_name:?} allows only one container"),
+        ));
+    }
+
+    for host_port in service_definition
+        .ports
+        .iter()
+        .filter_map(|port| port.get_fixed_host_port())
+    {
+        problems.push((
+            PUBLISHED_PORT,
+            format!(
+                "Fixed host port {host_port} allows only one container; \
+                consider a reverse proxy in front"
+            ),
+        ));
+    }
+
+    if !is_start_first && replica_count <= 1 {
+        problems.push((
+            SINGLE_STOP_FIRST,
+            "Stop-first service with a single replica is unavailable during updates".into(),
+        ));
+    }
+
+    if let Some(image) = &service_definition.image {
+        if service_definition.build.is_none() && has_mutable_tag(image) {
+            problems.push((
+                MUTABLE_TAG,
+                format!("Image {image:?} has a mutable tag, so changes go unnoticed"),
+            ));
+        }
+    }
+
+    if service_definition.stop_grace_period.is_none() {
+        problems.push((
+            NO_STOP_GRACE_PERIOD,
+            "No stop_grace_period, so containers are killed 10 seconds after being asked \
+            to stop"
+                .into(),
+        ));
+    }
+
+    problems
+}
+
+// A tag is mutable if missing, which means "latest", or "latest" itself. Images
+// pinned by digest are immutable.
+fn has_mutable_tag(image: &str) -> bool {
+    if image.contains('@') {
+        return false;
+    }
+    let name = image.rsplit_once('/').map_or(image, |(_, name)| name);
+    match name.split_once(':') {
+        None => true,
+        Some((_, tag)) => tag == "latest",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case::test_case("caddy", true; "no tag")]
+    #[test_case::test_case("caddy:latest", true; "latest")]
+    #[test_case::test_case("docker.io/caddy:2-alpine", false; "version")]
+    #[test_case::test_case("localhost:5000/caddy", true; "registry port")]
+    #[test_case::test_case("caddy@sha256:abc", false; "digest")]
+    fn has_mutable_tag_handles(image: &str, expected: bool) {
+        assert_eq!(has_mutable_tag(image), expected)
+    }
+
+    #[test]
+    fn get_problems_handles() {
+        let services = serde_json::from_value(serde_json::json!({
+            "api": {
+                "deploy": {"update_config": {"order": "start-first"}},
+                "healthcheck": {"test": ["CMD", "true"]},
+                "image": "docker.io/caddy:2-alpine",
+                "stop_grace_period": "30s",
+            },
+            "proxy": {
+                "container_name": "proxy",
+                "image": "caddy",
+                "ports": [{"host_ip": "127.0.0.1", "published": "8080", "target": 80}],
+            },
+            "worker": {
+                "build": {"context": "."},
+                "deploy": {"replicas": 2, "update_config": {"order": "start-first"}},
+                "healthcheck": {"disable": true},
+                "image": "worker",
+                "stop_grace_period": "1m",
+            },
+            "web": {
+                "image": "nginx:1.27",
+                "stop_grace_period": "30s",
+                "x-wheelsticks": {
+                    "readiness": {"http": {"port": 80}},
+                    "update_order": "start-first",
+                },
+            },
+        }))
+        .unwrap();
+
+        assert_eq!(
+            get_problems(&services)
+                .iter()
+                .map(|problem| (problem.service_name.as_str(), problem.rule))
+                .collect::<Vec<_>>(),
+            [
+                ("proxy", CONTAINER_NAME),
+                ("proxy", PUBLISHED_PORT),
+                ("proxy", SINGLE_STOP_FIRST),
+                ("proxy", MUTABLE_TAG),
+                ("proxy", NO_STOP_GRACE_PERIOD),
+                ("worker", NO_HEALTHCHECK),
+            ],
+        )
+    }
+}
@@ -1,9 +1,62 @@
+use super::http;
+use super::log;
 use anyhow::Context;
 use serde::de;
+use std::fmt;
 use std::io;
+use std::io::Read;
 use std::io::Write;
 use std::process;
 use std::thread;
+use std::time;
+
+pub struct RetryPolicy {
+    pub attempts: u16,
+    pub backoff: time::Duration,
+    pub exit_codes: Vec<i32>,
+    pub stderr_patterns: Vec<String>,
+}
+
+// Stderr is left to the terminal, keeping progress displays, unless retrying
+// on stderr patterns requires capturing it.
+#[derive(Clone, Copy)]
+pub enum Stderr {
+    Inherit,
+    // Forwarded live while also captured.
+    Tee,
+}
+
+#[derive(Debug)]
+pub struct StatusError {
+    pub status: process::ExitStatus,
+    pub stderr: String,
+}
+
+impl fmt::Display for StatusError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "{}", self.status)
+    }
+}
+
+impl std::error::Error for StatusError {}
+
+// Context of errors of commands, for logs to show the command in a field.
+#[derive(Debug)]
+struct CommandContext {
+    command: String,
+    has_run: bool,
+}
+
+impl fmt::Display for CommandContext {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        let command = &self.command;
+        if self.has_run {
+            write!(formatter, "Unable to evaluate result of command: {command}")
+        } else {
+            write!(formatter, "Unable to run command: {command}")
+        }
+    }
+}
 
 pub fn piped_ok(
     writer: &mut process::Command,
@@ -14,22 +67,70 @@ pub fn piped_ok(
         process::Command::spawn,
         |mut child| {
             let stdout = child.stdout.take().context("Unable to open stdout")?;
-            status_ok(reader.stdin(stdout))?;
+            status_ok(reader.stdin(stdout), Stderr::Inherit)?;
             wait_ok(&mut child)
         },
     )
 }
 
-pub fn status_ok(command: &mut process::Command) -> anyhow::Result<()> {
-    go(command, process::Command::status, |status| {
-        if status.success() {
+pub fn status_ok(command: &mut process::Command, stderr: Stderr) -> anyhow::Result<()> {
+    go(command, output(stderr), |output| {
+        if output.status.success() {
             Ok(())
         } else {
-            status_error(status)
+            status_error(output)
         }
     })
 }
 
+// Like `status_ok`, but kills the command after the timeout. Stderr is only
+// captured for the error as failures are expected, like of a probe.
+pub fn status_ok_within(
+    command: &mut process::Command,
+    timeout: time::Duration,
+) -> anyhow::Result<()> {
+    go(
+        command
+            .stdout(process::Stdio::null())
+            .stderr(process::Stdio::piped()),
+        process::Command::spawn,
+        |mut child| {
+            let stderr = child.stderr.take().map(|mut stderr| {
+                thread::spawn(move || {
+                    let mut captured = vec![];
+                    let _ = stderr.read_to_end(&mut captured);
+                    captured
+                })
+            });
+            let deadline = time::Instant::now() + timeout;
+
+            let status = loop {
+                if let Some(status) = child.try_wait().context("Unable to wait")? {
+                    break status;
+                }
+                if time::Instant::now() >= deadline {
+                    let _ = child.kill();
+                    let _ = child.wait();
+                    anyhow::bail!("Timed out after {timeout:?}");
+                }
+                thread::sleep(time::Duration::from_millis(10));
+            };
+
+            if status.success() {
+                Ok(())
+            } else {
+                status_error(process::Output {
+                    status,
+                    stdout: vec![],
+                    stderr: stderr
+                        .map(|stderr| stderr.join().unwrap_or_default())
+                        .unwrap_or_default(),
+                })
+            }
+        },
+    )
+}
+
 pub fn stdin_ok(input: &'static [u8], command: &mut process::Command) -> anyhow::Result<()> {
     go(
         command.stdin(process::Stdio::piped()),
@@ -42,16 +143,19 @@ pub fn stdin_ok(input: &'static [u8], command: &mut process::Command) -> anyhow:
     )
 }
 
-pub fn stdout_json<T: de::DeserializeOwned>(command: &mut process::Command) -> anyhow::Result<T> {
+pub fn stdout_json<T: de::DeserializeOwned>(
+    command: &mut process::Command,
+    stderr: Stderr,
+) -> anyhow::Result<T> {
     go(
-        command.stderr(process::Stdio::inherit()),
-        process::Command::output,
+        command.stdout(process::Stdio::piped()),
+        output(stderr),
         |output| {
             if output.status.success() {
                 serde_json::from_slice(&output.stdout)
                     .context("Unable to deserialize JSON from stdout")
             } else {
-                status_error(output.status)
+                status_error(output)
             }
         },
     )
@@ -59,10 +163,11 @@ pub fn stdout_json<T: de::DeserializeOwned>(command: &mut process::Command) -> a
 
 pub fn stdout_table<const N: usize>(
     command: &mut process::Command,
+    stderr: Stderr,
 ) -> anyhow::Result<Vec<[String; N]>> {
     go(
-        command.stderr(process::Stdio::inherit()),
-        process::Command::output,
+        command.stdout(process::Stdio::piped()),
+        output(stderr),
         |output| {
             if output.status.success() {
                 let table =
@@ -88,26 +193,78 @@ pub fn stdout_table<const N: usize>(
                     })
                     .collect()
             } else {
-                status_error(output.status)
+                status_error(output)
+            }
+        },
+    )
+}
+
+// Captures stdout and stderr interleaved like on a terminal, for output that
+// is meant for humans, like container logs.
+pub fn combined_output_utf8(command: &mut process::Command) -> anyhow::Result<String> {
+    go(
+        command,
+        |command| {
+            let (mut reader, writer) = io::pipe()?;
+            let child = command.stdout(writer.try_clone()?).stderr(writer).spawn();
+            // Closes the ends for writing held by the command, otherwise
+            // reading would never finish.
+            command
+                .stdout(process::Stdio::null())
+                .stderr(process::Stdio::null());
+            let mut child = child?;
+
+            let mut output = vec![];
+            reader.read_to_end(&mut output)?;
+            Ok((child.wait()?, output))
+        },
+        |(status, output)| {
+            if status.success() {
+                Ok(String::from_utf8_lossy(&output).into_owned())
+            } else {
+                status_error(process::Output {
+                    status,
+                    stdout: vec![],
+                    stderr: output,
+                })
             }
         },
     )
 }
 
-pub fn stdout_utf8(command: &mut process::Command) -> anyhow::Result<String> {
+pub fn stdout_utf8(command: &mut process::Command, stderr: Stderr) -> anyhow::Result<String> {
     go(
-        command.stderr(process::Stdio::inherit()),
-        process::Command::output,
+        command.stdout(process::Stdio::piped()),
+        output(stderr),
         |output| {
             if output.status.success() {
                 String::from_utf8(output.stdout).context("Stdout is not valid UTF-8")
             } else {
-                status_error(output.status)
+                status_error(output)
             }
         },
     )
 }
 
+// Commands run by `run` are to handle stderr as passed, which captures it if
+// needed for the stderr patterns of the policy.
+pub fn retry_safe<T>(
+    policy: &RetryPolicy,
+    run: impl FnMut(Stderr) -> anyhow::Result<T>,
+) -> anyhow::Result<T> {
+    retry(policy, run, || Ok(None))
+}
+
+// For steps that must not simply be repeated, `is_done` first re-checks the
+// actual state, which may show that a failed attempt had an effect after all.
+pub fn retry_unsafe(
+    policy: &RetryPolicy,
+    run: impl FnMut(Stderr) -> anyhow::Result<()>,
+    mut is_done: impl FnMut() -> anyhow::Result<bool>,
+) -> anyhow::Result<()> {
+    retry(policy, run, || Ok(is_done()?.then_some(())))
+}
+
 fn go<
     F: FnOnce(&mut process::Command) -> io::Result<T>,
     G: FnOnce(T) -> anyhow::Result<U>,
@@ -118,16 +275,88 @@ fn go<
     run: F,
     evaluate: G,
 ) -> anyhow::Result<U> {
+    log::debug!(
+        fields: log::Fields {
+            command: Some(format!("{command:?}")),
+            ..Default::default()
+        };
+        "Running command: {command:?}"
+    );
     match run(command) {
-        Err(error) => Err(anyhow::anyhow!(error))
-            .with_context(|| format!("Unable to run command: {command:?}")),
-        Ok(value) => evaluate(value)
-            .with_context(|| format!("Unable to evaluate result of command: {command:?}")),
+        Err(error) => Err(anyhow::anyhow!(error)).with_context(|| CommandContext {
+            command: format!("{command:?}"),
+            has_run: false,
+        }),
+        Ok(value) => evaluate(value).with_context(|| CommandContext {
+            command: format!("{command:?}"),
+            has_run: true,
+        }),
+    }
+}
+
+// Command whose failure caused the error, if any.
+pub fn get_failed_command(error: &anyhow::Error) -> Option<String> {
+    error
+        .downcast_ref::<CommandContext>()
+        .map(|context| context.command.clone())
+}
+
+// Runs the command like `process::Command::output` but only captures stdout if
+// piped by the caller.
+fn output(stderr: Stderr) -> impl FnOnce(&mut process::Command) -> io::Result<process::Output> {
+    move |command| match stderr {
+        Stderr::Inherit => command
+            .stderr(process::Stdio::inherit())
+            .spawn()?
+            .wait_with_output(),
+        Stderr::Tee => output_teeing_stderr(command),
+    }
+}
+
+// Runs the command like `process::Command::output` but forwards stderr live
+// while also capturing it, so failures can be matched against retry patterns.
+fn output_teeing_stderr(command: &mut process::Command) -> io::Result<process::Output> {
+    let mut child = command.stderr(process::Stdio::piped()).spawn()?;
+
+    let stderr = child.stderr.take().map(|mut stderr| {
+        thread::spawn(move || {
+            let mut captured = vec![];
+            let mut buffer = [0; 4096];
+            loop {
+                match stderr.read(&mut buffer) {
+                    Ok(0) | Err(_) => break captured,
+                    Ok(count) => {
+                        let _ = io::stderr().write_all(&buffer[..count]);
+                        captured.extend_from_slice(&buffer[..count]);
+                    }
+                }
+            }
+        })
+    });
+
+    let mut stdout = vec![];
+    if let Some(mut child_stdout) = child.stdout.take() {
+        child_stdout.read_to_end(&mut stdout)?;
     }
+
+    let status = child.wait()?;
+    let stderr = stderr
+        .map(|stderr| stderr.join().unwrap_or_default())
+        .unwrap_or_default();
+
+    Ok(process::Output {
+        status,
+        stdout,
+        stderr,
+    })
 }
 
-fn status_error<T>(status: process::ExitStatus) -> anyhow::Result<T> {
-    Err(anyhow::anyhow!("{status}"))
+fn status_error<T>(output: process::Output) -> anyhow::Result<T> {
+    Err(StatusError {
+        status: output.status,
+        stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+    }
+    .into())
 }
 
 fn wait_ok(child: &mut process::Child) -> anyhow::Result<()> {
@@ -136,10 +365,77 @@ fn wait_ok(child: &mut process::Child) -> anyhow::Result<()> {
     if status.success() {
         Ok(())
     } else {
-        status_error(status)
+        status_error(process::Output {
+            status,
+            stdout: vec![],
+            stderr: vec![],
+        })
     }
 }
 
+fn retry<T>(
+    policy: &RetryPolicy,
+    mut run: impl FnMut(Stderr) -> anyhow::Result<T>,
+    mut recheck: impl FnMut() -> anyhow::Result<Option<T>>,
+) -> anyhow::Result<T> {
+    let mut attempt = 1;
+
+    let stderr = if policy.stderr_patterns.is_empty() {
+        Stderr::Inherit
+    } else {
+        Stderr::Tee
+    };
+
+    loop {
+        match run(stderr) {
+            Ok(value) => break Ok(value),
+            Err(error) if attempt < policy.attempts && is_retryable(policy, &error) => {
+                let delay = policy
+                    .backoff
+                    .saturating_mul(2_u32.saturating_pow((attempt - 1).into()));
+                log::warn!(
+                    fields: log::Fields {
+                        command: get_failed_command(&error),
+                        ..Default::default()
+                    };
+                    "Attempt {attempt} of {} failed, retrying in {delay:?}: {error:#}",
+                    policy.attempts,
+                );
+                thread::sleep(delay);
+                attempt += 1;
+
+                if let Some(value) = recheck()? {
+                    log::debug!("Actual state shows that failed attempt succeeded.");
+                    break Ok(value);
+                }
+            }
+            Err(error) => break Err(error),
+        }
+    }
+}
+
+// Failures of the Engine API are retryable if the connection failed or the
+// server had an error, regardless of the exit codes and stderr patterns.
+fn is_retryable(policy: &RetryPolicy, error: &anyhow::Error) -> bool {
+    error.chain().any(|cause| {
+        if let Some(StatusError { status, stderr }) = cause.downcast_ref() {
+            let is_any_failure = policy.exit_codes.is_empty() && policy.stderr_patterns.is_empty();
+            let is_exit_code_match = status
+                .code()
+                .is_some_and(|code| policy.exit_codes.contains(&code));
+            let is_stderr_match = policy
+                .stderr_patterns
+                .iter()
+                .any(|pattern| stderr.contains(pattern));
+            is_any_failure || is_exit_code_match || is_stderr_match
+        } else if let Some(error) = cause.downcast_ref::<http::StatusError>() {
+            error.is_server_error()
+        } else {
+            cause.is::<http::TransportError>()
+        }
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -161,7 +457,24 @@ mod tests {
     #[test_case::test_case(bash("true"), true; "success")]
     #[test_case::test_case(bash("false"), false; "failure")]
     fn status_ok_handles(mut command: process::Command, expected: bool) {
-        assert_eq!(status_ok(&mut command).is_ok(), expected)
+        assert_eq!(status_ok(&mut command, Stderr::Inherit).is_ok(), expected)
+    }
+
+    #[test_case::test_case(invalid_program_(), None; "invalid program")]
+    #[test_case::test_case(bash("echo 'a'; echo 'b' >&2; echo 'c'"), Some("a\nb\nc\n"); "success")]
+    #[test_case::test_case(bash("echo 'a' >&2; false"), None; "failure")]
+    fn combined_output_utf8_handles(mut command: process::Command, expected: Option<&str>) {
+        assert_eq!(combined_output_utf8(&mut command).ok().as_deref(), expected)
+    }
+
+    #[test_case::test_case(invalid_program_(), false; "invalid program")]
+    #[test_case::test_case(bash("true"), true; "success")]
+    #[test_case::test_case(bash("false"), false; "failure")]
+    #[test_case::test_case(bash("sleep 5"), false; "timeout")]
+    fn status_ok_within_handles(mut command: process::Command, expected: bool) {
+        let result = status_ok_within(&mut command, time::Duration::from_millis(200));
+
+        assert_eq!(result.is_ok(), expected)
     }
 
     #[test_case::test_case(invalid_program_(), false; "invalid program")]
@@ -175,7 +488,7 @@ mod tests {
     #[test_case::test_case(bash("false"), None; "failure")]
     #[test_case::test_case(bash("echo '\"Hi\"'"), Some("Hi".into()); "success")]
     fn stdout_json_handles(mut command: process::Command, expected: Option<String>) {
-        assert_eq!(stdout_json(&mut command).ok(), expected)
+        assert_eq!(stdout_json(&mut command, Stderr::Inherit).ok(), expected)
     }
 
     #[test_case::test_case(invalid_program_(), None; "invalid program")]
@@ -189,14 +502,119 @@ mod tests {
         "success"
     )]
     fn stdout_table_handles(mut command: process::Command, expected: Option<Vec<[String; 3]>>) {
-        assert_eq!(stdout_table(&mut command).ok(), expected)
+        assert_eq!(stdout_table(&mut command, Stderr::Inherit).ok(), expected)
     }
 
     #[test_case::test_case(invalid_program_(), None; "invalid program")]
     #[test_case::test_case(bash("false"), None; "failure")]
     #[test_case::test_case(bash("printf 'Hi'"), Some("Hi".into()); "success")]
     fn stdout_utf8_handles(mut command: process::Command, expected: Option<String>) {
-        assert_eq!(stdout_utf8(&mut command).ok(), expected)
+        assert_eq!(stdout_utf8(&mut command, Stderr::Inherit).ok(), expected)
+    }
+
+    #[test_case::test_case(bash("exit 3"), &[], &[], 3; "any failure")]
+    #[test_case::test_case(bash("exit 3"), &[3], &[], 3; "matching exit code")]
+    #[test_case::test_case(bash("exit 3"), &[4], &[], 1; "other exit code")]
+    #[test_case::test_case(bash("echo 'Conn reset' >&2; exit 1"), &[], &["reset"], 3; "matching stderr")]
+    #[test_case::test_case(bash("echo 'No such image' >&2; exit 1"), &[], &["reset"], 1; "other stderr")]
+    #[test_case::test_case(invalid_program_(), &[], &[], 1; "invalid program")]
+    fn retry_safe_handles(
+        mut command: process::Command,
+        exit_codes: &[i32],
+        stderr_patterns: &[&str],
+        expected_attempts: u16,
+    ) {
+        let policy = retry_policy(3, exit_codes, stderr_patterns);
+        let mut attempts = 0;
+
+        let result = retry_safe(&policy, |stderr| {
+            attempts += 1;
+            status_ok(&mut command, stderr)
+        });
+
+        assert!(result.is_err());
+        assert_eq!(attempts, expected_attempts)
+    }
+
+    #[test_case::test_case(&[], ""; "without stderr patterns")]
+    #[test_case::test_case(&["reset"], "Conn reset\n"; "with stderr patterns")]
+    fn retry_safe_captures_stderr_only_for_patterns(stderr_patterns: &[&str], expected: &str) {
+        let policy = retry_policy(1, &[], stderr_patterns);
+
+        let error = retry_safe(&policy, |stderr| {
+            status_ok(&mut bash("echo 'Conn reset' >&2; exit 1"), stderr)
+        })
+        .unwrap_err();
+
+        let StatusError { stderr, .. } = error.downcast_ref().unwrap();
+        assert_eq!(stderr, expected)
+    }
+
+    #[test_case::test_case(Some(503), 3; "server error")]
+    #[test_case::test_case(Some(409), 1; "client error")]
+    #[test_case::test_case(None, 3; "transport error")]
+    fn retry_safe_handles_http_errors(status: Option<u16>, expected_attempts: u16) {
+        let policy = retry_policy(3, &[], &["reset"]);
+        let mut attempts = 0;
+
+        let result = retry_safe(&policy, |_| {
+            attempts += 1;
+            Err::<(), anyhow::Error>(match status {
+                None => http::TransportError(io::ErrorKind::ConnectionReset.into()).into(),
+                Some(status) => http::StatusError {
+                    message: "".into(),
+                    status,
+                }
+                .into(),
+            })
+            .context("Unable to request")
+        });
+
+        assert!(result.is_err());
+        assert_eq!(attempts, expected_attempts)
+    }
+
+    #[test_case::test_case(false, false, 3; "never done")]
+    #[test_case::test_case(true, true, 1; "done after failure")]
+    fn retry_unsafe_handles(is_done: bool, expected_ok: bool, expected_attempts: u16) {
+        let policy = retry_policy(3, &[], &[]);
+        let mut attempts = 0;
+
+        let result = retry_unsafe(
+            &policy,
+            |stderr| {
+                attempts += 1;
+                status_ok(&mut bash("false"), stderr)
+            },
+            || Ok(is_done),
+        );
+
+        assert_eq!(result.is_ok(), expected_ok);
+        assert_eq!(attempts, expected_attempts)
+    }
+
+    #[test_case::test_case(invalid_program_(); "invalid program")]
+    #[test_case::test_case(bash("false"); "failure")]
+    fn get_failed_command_handles(mut command: process::Command) {
+        let expected = format!("{command:?}");
+
+        let error = status_ok(&mut command, Stderr::Inherit)
+            .context("Unable to deploy")
+            .unwrap_err();
+
+        assert_eq!(get_failed_command(&error), Some(expected))
+    }
+
+    fn retry_policy(attempts: u16, exit_codes: &[i32], stderr_patterns: &[&str]) -> RetryPolicy {
+        RetryPolicy {
+            attempts,
+            backoff: time::Duration::ZERO,
+            exit_codes: exit_codes.into(),
+            stderr_patterns: stderr_patterns
+                .iter()
+                .map(|pattern| pattern.to_string())
+                .collect(),
+        }
     }
 
     fn invalid_program_() -> process::Command {
@@ -1,62 +1,63 @@
+use super::backend;
+use super::diagnose_container;
+use super::events;
 use super::model;
-use crate::command;
-use crate::docker;
-use crate::docker_compose;
+use super::probe_readiness;
+use super::write_backends_file;
+use super::Forward;
 use crate::log;
 use anyhow::Context;
 use std::collections;
+use std::time;
 
 pub fn go(
     In {
         actual_containers,
-        build,
+        backend,
         changes,
-        docker_cli,
-        docker_compose_cli,
+        desired_services,
         dry_run,
-        no_build,
+        events,
+        forwards,
         no_start,
-        pull,
-        quiet_pull,
-        remove_orphans,
-        renew_anon_volumes,
-        service_names,
-        timeout,
-        wait,
-        wait_timeout,
+        switch_aliases,
     }: In,
 ) -> anyhow::Result<()> {
     let mut state = new_rolling_state(actual_containers);
-
-    if build {
-        build_images(service_names, dry_run, docker_compose_cli)?;
-    }
+    let options = RolloutOptions {
+        desired_services,
+        forwards,
+        no_start,
+        switch_aliases,
+    };
 
     for change in changes {
         let summary = summarize_change(change);
+        let fields = get_log_fields(change);
 
         if dry_run {
-            log::info!("Would {summary}.");
+            log::info!(fields: fields; "Would {summary}.");
         } else {
-            log::info!("Going to {summary}.");
-            apply_change(
-                change,
-                ChangeOptions {
-                    no_build,
-                    no_start,
-                    pull,
-                    quiet_pull,
-                    remove_orphans,
-                    renew_anon_volumes,
-                    timeout,
-                    wait,
-                    wait_timeout,
+            log::info!(fields: fields; "Going to {summary}.");
+            events.send(events::Event::StepStarted { change });
+            let start = time::Instant::now();
+
+            let result = apply_change(change, backend, options, &mut state)
+                .with_context(|| format!("Unable to {summary}"));
+
+            let duration_seconds = start.elapsed().as_secs_f64();
+            events.send(match &result {
+                Ok(()) => events::Event::StepFinished {
+                    change,
+                    duration_seconds,
                 },
-                docker_cli,
-                docker_compose_cli,
-                &mut state,
-            )
-            .with_context(|| format!("Unable to {summary}"))?;
+                Err(error) => events::Event::StepFailed {
+                    change,
+                    duration_seconds,
+                    error: format!("{error:#}"),
+                },
+            });
+            result?;
         }
     }
 
@@ -65,40 +66,30 @@ pub fn go(
 
 pub struct In<'a> {
     pub actual_containers: &'a model::ActualContainers,
-    pub build: bool,
+    pub backend: &'a dyn backend::Backend,
     pub changes: &'a [model::ServiceContainerChange],
-    pub docker_cli: &'a docker::Cli<'a>,
-    pub docker_compose_cli: &'a docker_compose::Cli<'a>,
+    pub desired_services: &'a model::DesiredServices,
     pub dry_run: bool,
-    pub no_build: bool,
+    pub events: events::Scope<'a>,
+    pub forwards: &'a [Forward],
+    // Like `docker compose up --no-start`, only creates new containers.
     pub no_start: bool,
-    pub pull: Option<&'a str>,
-    pub quiet_pull: bool,
-    pub remove_orphans: bool,
-    pub renew_anon_volumes: bool,
-    pub service_names: &'a collections::BTreeSet<String>,
-    pub timeout: Option<&'a str>,
-    pub wait: bool,
-    pub wait_timeout: Option<&'a str>,
+    pub switch_aliases: bool,
 }
 
-struct RollingState<'a> {
-    service_container_count: collections::BTreeMap<&'a str, u16>,
+#[derive(Clone, Copy)]
+struct RolloutOptions<'a> {
+    desired_services: &'a model::DesiredServices,
+    forwards: &'a [Forward],
+    no_start: bool,
+    switch_aliases: bool,
 }
 
-struct ChangeOptions<'a> {
-    no_build: bool,
-    no_start: bool,
-    pull: Option<&'a str>,
-    quiet_pull: bool,
-    remove_orphans: bool,
-    renew_anon_volumes: bool,
-    timeout: Option<&'a str>,
-    wait: bool,
-    wait_timeout: Option<&'a str>,
+struct RollingState<'a> {
+    service_container_count: collections::BTreeMap<&'a str, u16>,
 }
 
-fn new_rolling_state(actual_containers: &model::ActualContainers) -> RollingState {
+fn new_rolling_state(actual_containers: &model::ActualContainers) -> RollingState<'_> {
     let mut service_container_count = collections::BTreeMap::new();
 
     for container in actual_containers {
@@ -113,19 +104,34 @@ fn new_rolling_state(actual_containers: &model::ActualContainers) -> RollingStat
     }
 }
 
-fn build_images(
-    service_names: &collections::BTreeSet<String>,
-    dry_run: bool,
-    docker_compose_cli: &docker_compose::Cli,
-) -> anyhow::Result<()> {
-    log::debug!("Building services.");
-    command::status_ok(
-        docker_compose_cli
-            .command()
-            .args(dry_run.then_some("--dry-run").iter())
-            .args(["build", "--"])
-            .args(service_names),
-    )
+fn get_log_fields(change: &model::ServiceContainerChange) -> log::Fields<'_> {
+    match change {
+        model::ServiceContainerChange::Add { service_name, .. } => log::Fields {
+            change_kind: Some("add"),
+            service: Some(service_name),
+            ..Default::default()
+        },
+        model::ServiceContainerChange::Keep {
+            container_id,
+            service_name,
+            ..
+        } => log::Fields {
+            change_kind: Some("keep"),
+            container_id: Some(container_id),
+            service: Some(service_name),
+            ..Default::default()
+        },
+        model::ServiceContainerChange::Remove {
+            container_id,
+            service_name,
+            ..
+        } => log::Fields {
+            change_kind: Some("remove"),
+            container_id: Some(container_id),
+            service: Some(service_name),
+            ..Default::default()
+        },
+    }
 }
 
 fn summarize_change(change: &model::ServiceContainerChange) -> String {
@@ -178,14 +184,38 @@ fn summarize_service(service_name: &str, service_config_hash: &str) -> String {
 
 fn apply_change<'a>(
     change: &'a model::ServiceContainerChange,
-    change_options: ChangeOptions,
-    docker_cli: &docker::Cli,
-    docker_compose_cli: &docker_compose::Cli,
+    backend: &dyn backend::Backend,
+    options: RolloutOptions,
     state: &mut RollingState<'a>,
 ) -> anyhow::Result<()> {
     match change {
         model::ServiceContainerChange::Add { service_name, .. } => {
-            add_container(service_name, change_options, docker_compose_cli, state)
+            let settings = get_settings(service_name, options);
+            let service_names = collections::BTreeSet::from([service_name.clone()]);
+            let old_containers = backend.list_containers(&service_names)?;
+
+            let result = if options.switch_aliases {
+                add_container_switching_aliases(
+                    service_name,
+                    &old_containers,
+                    settings,
+                    options.no_start,
+                    backend,
+                    state,
+                )
+            } else {
+                add_container(service_name, &old_containers, settings, backend, state)
+            };
+            if let Err(error) = result {
+                return Err(diagnose_new_containers(
+                    error,
+                    &service_names,
+                    &old_containers,
+                    backend,
+                ));
+            }
+
+            write_backends_files(service_name, None, backend, options.forwards)
         }
 
         model::ServiceContainerChange::Keep { .. } => Ok(()),
@@ -194,69 +224,256 @@ fn apply_change<'a>(
             container_id,
             service_name,
             ..
-        } => remove_container(service_name, container_id, docker_cli, state),
+        } => remove_container(service_name, container_id, backend, options, state),
     }
 }
 
+fn get_settings<'a>(
+    service_name: &str,
+    options: RolloutOptions<'a>,
+) -> Option<&'a model::ServiceSettings> {
+    options
+        .desired_services
+        .get(service_name)
+        .map(|service_definition| &service_definition.settings)
+}
+
 fn add_container<'a>(
     service_name: &'a str,
-    ChangeOptions {
-        no_build,
-        no_start,
-        pull,
-        quiet_pull,
-        remove_orphans,
-        renew_anon_volumes,
-        timeout,
-        wait,
-        wait_timeout,
-    }: ChangeOptions,
-    docker_compose_cli: &docker_compose::Cli,
+    old_containers: &model::ActualContainers,
+    settings: Option<&model::ServiceSettings>,
+    backend: &dyn backend::Backend,
+    state: &mut RollingState<'a>,
+) -> anyhow::Result<()> {
+    let wait_timeout = settings.and_then(|settings| settings.wait_timeout);
+    let readiness = settings.and_then(|settings| settings.readiness.as_ref());
+    let container_count = state
+        .service_container_count
+        .entry(service_name)
+        .and_modify(|count| *count += 1)
+        .or_insert(1);
+
+    log::debug!(
+        fields: log::Fields {
+            service: Some(service_name),
+            ..Default::default()
+        };
+        "Scaling service {service_name:?} to {container_count} instances."
+    );
+    let Some(readiness) = readiness else {
+        return backend.scale_up(service_name, *container_count, wait_timeout);
+    };
+
+    let service_names = collections::BTreeSet::from([service_name.into()]);
+    backend.scale_up(service_name, *container_count, wait_timeout)?;
+
+    for container in backend.list_containers(&service_names)? {
+        if !old_containers.contains(&container) {
+            probe_readiness::go(probe_readiness::In {
+                backend,
+                container_id: &container.container_id,
+                readiness,
+            })?;
+        }
+    }
+    Ok(())
+}
+
+// The new container joins its networks under a temporary alias until it is
+// ready, then takes over the aliases it was created with, like the service
+// name. Thus, clients never resolve a container that is still starting. With
+// `no_start`, the container is only created.
+fn add_container_switching_aliases<'a>(
+    service_name: &'a str,
+    old_containers: &model::ActualContainers,
+    settings: Option<&model::ServiceSettings>,
+    no_start: bool,
+    backend: &dyn backend::Backend,
     state: &mut RollingState<'a>,
 ) -> anyhow::Result<()> {
+    let wait_timeout = settings.and_then(|settings| settings.wait_timeout);
+    let readiness = settings.and_then(|settings| settings.readiness.as_ref());
     let container_count = state
         .service_container_count
         .entry(service_name)
         .and_modify(|count| *count += 1)
         .or_insert(1);
+    let service_names = collections::BTreeSet::from([service_name.into()]);
+
+    log::debug!(
+        fields: log::Fields {
+            service: Some(service_name),
+            ..Default::default()
+        };
+        "Creating containers of service {service_name:?} up to {container_count} instances."
+    );
+    backend.create_containers(service_name, *container_count)?;
+    if no_start {
+        return Ok(());
+    }
+
+    let temporary_alias = format!("{service_name}-next");
+    for container in backend.list_containers(&service_names)? {
+        if old_containers.contains(&container) {
+            continue;
+        }
+        let container_id = &container.container_id;
+        let container = summarize_container(container_id);
+        let fields = || log::Fields {
+            container_id: Some(container_id),
+            service: Some(service_name),
+            ..Default::default()
+        };
+        // Like one restarted meanwhile, a running container may serve clients.
+        if backend.inspect_state(container_id)?.status == "running" {
+            log::debug!(fields: fields(); "Leaving running {container} alone.");
+            continue;
+        }
+
+        let network_attachments = list_user_defined_networks(container_id, backend)?;
+        let temporary_attachments = network_attachments
+            .iter()
+            .map(|network_attachment| backend::NetworkAttachment {
+                aliases: vec![temporary_alias.clone()],
+                ..network_attachment.clone()
+            })
+            .collect::<Vec<_>>();
+
+        log::debug!(fields: fields(); "Starting {container} as {temporary_alias:?}.");
+        reconnect_networks(container_id, &temporary_attachments, backend)?;
+        backend.start_container(container_id, wait_timeout)?;
+        if let Some(readiness) = readiness {
+            log::debug!(fields: fields(); "Probing readiness of {container}.");
+            probe_readiness::go(probe_readiness::In {
+                backend,
+                container_id,
+                readiness,
+            })?;
+        }
 
-    log::debug!("Scaling service {service_name:?} to {container_count} instances.");
-    command::status_ok(
-        docker_compose_cli
-            .command()
-            .args(["up", "--detach"])
-            .args(no_build.then_some("--no-build").iter())
-            .args(["--no-deps", "--no-recreate"])
-            .args(no_start.then_some("--no-start").iter())
-            .args(pull.iter().flat_map(|pull| ["--pull", pull]))
-            .args(quiet_pull.then_some("--quiet-pull").iter())
-            .args(remove_orphans.then_some("--remove-orphans").iter())
-            .args(renew_anon_volumes.then_some("--renew-anon-volumes").iter())
-            .args(["--scale", &format!("{service_name}={container_count}")])
-            .args(timeout.iter().flat_map(|timeout| ["--timeout", timeout]))
-            .args(wait.then_some("--wait").iter())
-            .args(
-                wait_timeout
-                    .iter()
-                    .flat_map(|wait_timeout| ["--wait-timeout", wait_timeout]),
-            )
-            .args(["--", service_name]),
-    )
+        log::debug!(fields: fields(); "Switching aliases to {container}.");
+        reconnect_networks(container_id, &network_attachments, backend)?;
+    }
+
+    Ok(())
+}
+
+// Adds the diagnosis of each new container to the error, which is what anyone
+// without access to the host needs to find the cause.
+fn diagnose_new_containers(
+    mut error: anyhow::Error,
+    service_names: &collections::BTreeSet<String>,
+    old_containers: &model::ActualContainers,
+    backend: &dyn backend::Backend,
+) -> anyhow::Error {
+    let new_containers = match backend.list_containers(service_names) {
+        Ok(containers) => containers,
+        Err(list_error) => {
+            log::warn!("Unable to list containers for diagnosis: {list_error:#}");
+            return error;
+        }
+    };
+
+    for container in new_containers.difference(old_containers) {
+        let container_id = &container.container_id;
+        let container = summarize_container(container_id);
+        let diagnosis = diagnose_container::go(diagnose_container::In {
+            backend,
+            container_id,
+        })
+        .unwrap_or_else(|diagnosis_error| format!("Unable to diagnose: {diagnosis_error:#}"));
+        error = error.context(format!("Diagnosis of new {container}:\n{diagnosis}\n"));
+    }
+
+    error
+}
+
+fn write_backends_files(
+    service_name: &str,
+    excluded_container_id: Option<&str>,
+    backend: &dyn backend::Backend,
+    forwards: &[Forward],
+) -> anyhow::Result<()> {
+    forwards
+        .iter()
+        .filter(|forward| forward.service_name == service_name)
+        .try_for_each(|forward| {
+            write_backends_file::go(write_backends_file::In {
+                backend,
+                excluded_container_id,
+                forward,
+            })
+        })
+}
+
+// Containers with a network mode like "host" are only in a predefined network,
+// if any, which has no aliases to switch and cannot be left.
+fn list_user_defined_networks(
+    container_id: &str,
+    backend: &dyn backend::Backend,
+) -> anyhow::Result<Vec<backend::NetworkAttachment>> {
+    let mut network_attachments = backend.list_networks(container_id)?;
+    network_attachments.retain(|network_attachment| {
+        !backend::PREDEFINED_NETWORK_NAMES.contains(&network_attachment.network_name.as_str())
+    });
+    Ok(network_attachments)
+}
+
+fn reconnect_networks(
+    container_id: &str,
+    network_attachments: &[backend::NetworkAttachment],
+    backend: &dyn backend::Backend,
+) -> anyhow::Result<()> {
+    for network_attachment in network_attachments {
+        backend.disconnect_network(container_id, &network_attachment.network_name)?;
+        backend.connect_network(container_id, network_attachment)?;
+    }
+    Ok(())
 }
 
 fn remove_container<'a>(
     service_name: &'a str,
     container_id: &str,
-    docker_cli: &docker::Cli,
+    backend: &dyn backend::Backend,
+    options: RolloutOptions,
     state: &mut RollingState<'a>,
 ) -> anyhow::Result<()> {
     let container = summarize_container(container_id);
+    let fields = || log::Fields {
+        container_id: Some(container_id),
+        service: Some(service_name),
+        ..Default::default()
+    };
 
-    log::debug!("Stopping {container}.");
-    command::status_ok(docker_cli.command().args(["stop", "--", container_id]))?;
+    // Taking the container out of forwarding and leaving the networks first
+    // keeps new clients from reaching it while it shuts down.
+    write_backends_files(service_name, Some(container_id), backend, options.forwards)?;
+    if options.switch_aliases {
+        log::debug!(fields: fields(); "Disconnecting {container} from networks.");
+        for network_attachment in list_user_defined_networks(container_id, backend)? {
+            backend.disconnect_network(container_id, &network_attachment.network_name)?;
+        }
+    }
+
+    // Orphans have no settings.
+    let settings = get_settings(service_name, options);
+    if let Some(pre_stop) = settings.and_then(|settings| settings.pre_stop.as_ref()) {
+        // Like a failing pre-stop hook in Kubernetes, the container is stopped
+        // anyway.
+        log::debug!(fields: fields(); "Running pre-stop command in {container}.");
+        if let Err(error) = backend.exec_container(container_id, pre_stop) {
+            log::warn!(fields: fields(); "Pre-stop command failed in {container}: {error:#}");
+        }
+    }
+
+    log::debug!(fields: fields(); "Stopping {container}.");
+    backend.stop_container(
+        container_id,
+        settings.and_then(|settings| settings.stop_timeout),
+    )?;
 
-    log::debug!("Removing {container}.");
-    command::status_ok(docker_cli.command().args(["rm", "--", container_id]))?;
+    log::debug!(fields: fields(); "Removing {container}.");
+    backend.remove_container(container_id)?;
 
     state
         .service_container_count
@@ -265,3 +482,326 @@ fn remove_container<'a>(
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::super::events;
+    use super::super::fake_backend;
+    use super::super::plan_changes;
+    use super::*;
+
+    #[test_case::test_case(model::OperationOrder::StartFirst, &[
+        scale_up(2), stop(OLD_CONTAINER_ID), remove(OLD_CONTAINER_ID)
+    ]; "start first")]
+    #[test_case::test_case(model::OperationOrder::StopFirst, &[
+        stop(OLD_CONTAINER_ID), remove(OLD_CONTAINER_ID), scale_up(1)
+    ]; "stop first")]
+    fn handles(update_order: model::OperationOrder, expected: &[fake_backend::Operation]) {
+        let desired_services = new_desired_services(update_order);
+        let backend = new_backend(&desired_services);
+
+        roll_out(&desired_services, &backend, |_| {}).unwrap();
+
+        assert_eq!(backend.operations(), expected);
+        let running_hashes = backend
+            .running_containers()
+            .into_iter()
+            .map(|container| container.service_config_hash)
+            .collect::<Vec<_>>();
+        assert_eq!(running_hashes, [NEW_HASH]);
+    }
+
+    #[test]
+    fn keeps_old_container_if_new_one_is_unhealthy() {
+        let desired_services = new_desired_services(model::OperationOrder::StartFirst);
+        let backend = new_backend(&desired_services).with_unhealthy_service("x");
+
+        let result = roll_out(&desired_services, &backend, |_| {});
+
+        let error = format!("{:#}", result.unwrap_err());
+        assert!(error.contains(r#"health "unhealthy""#), "{error}");
+        assert!(
+            error.contains(&format!("Started {NEW_CONTAINER_ID}")),
+            "{error}"
+        );
+        assert_eq!(backend.operations(), [scale_up(2)]);
+        assert!(backend
+            .running_containers()
+            .iter()
+            .any(|container| container.container_id == OLD_CONTAINER_ID));
+    }
+
+    #[test]
+    fn handles_dry_run() {
+        let desired_services = new_desired_services(model::OperationOrder::StartFirst);
+        let backend = new_backend(&desired_services);
+
+        roll_out(&desired_services, &backend, |options| {
+            options.dry_run = true
+        })
+        .unwrap();
+
+        assert_eq!(backend.operations(), []);
+    }
+
+    #[test]
+    fn switches_aliases() {
+        let desired_services = new_desired_services(model::OperationOrder::StartFirst);
+        let backend = new_backend(&desired_services);
+        let new_container_id = format!("{:064x}", 1);
+
+        roll_out(&desired_services, &backend, |options| {
+            options.switch_aliases = true
+        })
+        .unwrap();
+
+        assert_eq!(
+            backend.operations(),
+            [
+                fake_backend::Operation::Create {
+                    container_count: 2,
+                    service_name: "x".into(),
+                },
+                disconnect(&new_container_id),
+                connect(&new_container_id, "x-next"),
+                fake_backend::Operation::Start {
+                    container_id: new_container_id.clone(),
+                },
+                disconnect(&new_container_id),
+                connect(&new_container_id, "x"),
+                disconnect(OLD_CONTAINER_ID),
+                stop(OLD_CONTAINER_ID),
+                remove(OLD_CONTAINER_ID),
+            ],
+        );
+        assert_eq!(backend.resolve("x"), [new_container_id]);
+    }
+
+    #[test]
+    fn switches_aliases_only_if_starting() {
+        let desired_services = new_desired_services(model::OperationOrder::StartFirst);
+        let backend = new_backend(&desired_services);
+
+        roll_out(&desired_services, &backend, |options| {
+            options.no_start = true;
+            options.switch_aliases = true;
+        })
+        .unwrap();
+
+        assert_eq!(
+            backend.operations(),
+            [
+                fake_backend::Operation::Create {
+                    container_count: 2,
+                    service_name: "x".into(),
+                },
+                disconnect(OLD_CONTAINER_ID),
+                stop(OLD_CONTAINER_ID),
+                remove(OLD_CONTAINER_ID),
+            ],
+        );
+    }
+
+    #[test]
+    fn switches_aliases_keeping_static_ip() {
+        let desired_services = new_desired_services(model::OperationOrder::StopFirst);
+        let backend = new_backend(&desired_services).with_static_ip("x", "172.16.0.2");
+
+        roll_out(&desired_services, &backend, |options| {
+            options.switch_aliases = true
+        })
+        .unwrap();
+
+        let ipv4_addresses = backend
+            .operations()
+            .into_iter()
+            .filter_map(|operation| match operation {
+                fake_backend::Operation::Connect {
+                    network_attachment, ..
+                } => Some(network_attachment.ipv4_address),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            ipv4_addresses,
+            [Some("172.16.0.2".into()), Some("172.16.0.2".into())]
+        );
+    }
+
+    #[test_case::test_case("host"; "host")]
+    #[test_case::test_case("none"; "none")]
+    #[test_case::test_case("service:db"; "service")]
+    fn switches_aliases_only_in_user_defined_networks(network_mode: &str) {
+        let desired_services = new_desired_services(model::OperationOrder::StopFirst);
+        let backend = new_backend(&desired_services).with_network_mode("x", network_mode);
+        let new_container_id = format!("{:064x}", 1);
+
+        roll_out(&desired_services, &backend, |options| {
+            options.switch_aliases = true
+        })
+        .unwrap();
+
+        assert_eq!(
+            backend.operations(),
+            [
+                stop(OLD_CONTAINER_ID),
+                remove(OLD_CONTAINER_ID),
+                fake_backend::Operation::Create {
+                    container_count: 1,
+                    service_name: "x".into(),
+                },
+                fake_backend::Operation::Start {
+                    container_id: new_container_id,
+                },
+            ],
+        );
+    }
+
+    #[test]
+    fn runs_pre_stop_command() {
+        let mut desired_services = new_desired_services(model::OperationOrder::StartFirst);
+        let pre_stop = vec!["nginx".to_string(), "-s".into(), "quit".into()];
+        desired_services.get_mut("x").unwrap().settings.pre_stop = Some(pre_stop.clone());
+        let backend = new_backend(&desired_services);
+
+        roll_out(&desired_services, &backend, |_| {}).unwrap();
+
+        assert_eq!(
+            backend.operations(),
+            [
+                scale_up(2),
+                fake_backend::Operation::Exec {
+                    command: pre_stop,
+                    container_id: OLD_CONTAINER_ID.into(),
+                },
+                stop(OLD_CONTAINER_ID),
+                remove(OLD_CONTAINER_ID),
+            ],
+        );
+    }
+
+    #[test_case::test_case(false, &[
+        scale_up(2), probe(NEW_CONTAINER_ID), stop(OLD_CONTAINER_ID), remove(OLD_CONTAINER_ID)
+    ]; "ready")]
+    #[test_case::test_case(true, &[
+        scale_up(2), probe(NEW_CONTAINER_ID), probe(NEW_CONTAINER_ID), probe(NEW_CONTAINER_ID)
+    ]; "unready")]
+    fn probes_readiness(is_unready: bool, expected: &[fake_backend::Operation]) {
+        let mut desired_services = new_desired_services(model::OperationOrder::StartFirst);
+        desired_services.get_mut("x").unwrap().settings.readiness = Some(model::Readiness {
+            image: "busybox".into(),
+            interval: time::Duration::ZERO,
+            probe: model::Probe::Tcp { port: 80 },
+            retries: 2,
+            timeout: time::Duration::from_secs(1),
+        });
+        let mut backend = new_backend(&desired_services);
+        if is_unready {
+            backend = backend.with_unready_service("x");
+        }
+
+        let result = roll_out(&desired_services, &backend, |_| {});
+
+        assert_eq!(result.is_err(), is_unready);
+        assert_eq!(backend.operations(), expected);
+    }
+
+    // Rolls out the desired services over the actual containers, with default
+    // options unless overridden.
+    fn roll_out(
+        desired_services: &model::DesiredServices,
+        backend: &fake_backend::FakeBackend,
+        override_options: impl FnOnce(&mut In),
+    ) -> anyhow::Result<()> {
+        let actual_containers = new_actual_containers();
+        let changes = plan_changes::go(&actual_containers, desired_services, false);
+        let events = events::Stream::default();
+        let mut options = In {
+            actual_containers: &actual_containers,
+            backend,
+            changes: &changes,
+            desired_services,
+            dry_run: false,
+            events: events.scope(None, None),
+            forwards: &[],
+            no_start: false,
+            switch_aliases: false,
+        };
+        override_options(&mut options);
+        go(options)
+    }
+
+    fn new_backend(desired_services: &model::DesiredServices) -> fake_backend::FakeBackend {
+        fake_backend::FakeBackend::new(&new_actual_containers(), desired_services)
+    }
+
+    const OLD_CONTAINER_ID: &str = "old-container-id";
+    const NEW_CONTAINER_ID: &str =
+        "0000000000000000000000000000000000000000000000000000000000000001";
+    const OLD_HASH: &str = "old-config-hash";
+    const NEW_HASH: &str = "new-config-hash";
+
+    fn new_actual_containers() -> model::ActualContainers {
+        [model::ActualContainer {
+            container_id: OLD_CONTAINER_ID.into(),
+            image_id: "".into(),
+            service_config_hash: OLD_HASH.into(),
+            service_name: "x".into(),
+        }]
+        .into()
+    }
+
+    fn new_desired_services(update_order: model::OperationOrder) -> model::DesiredServices {
+        [(
+            "x".into(),
+            model::DesiredServiceDefinition {
+                update_order,
+                ..model::DesiredServiceDefinition::new(NEW_HASH)
+            },
+        )]
+        .into()
+    }
+
+    fn scale_up(container_count: u16) -> fake_backend::Operation {
+        fake_backend::Operation::ScaleUp {
+            container_count,
+            service_name: "x".into(),
+        }
+    }
+
+    fn stop(container_id: &str) -> fake_backend::Operation {
+        fake_backend::Operation::Stop {
+            container_id: container_id.into(),
+        }
+    }
+
+    fn probe(container_id: &str) -> fake_backend::Operation {
+        fake_backend::Operation::Probe {
+            container_id: container_id.into(),
+        }
+    }
+
+    fn remove(container_id: &str) -> fake_backend::Operation {
+        fake_backend::Operation::Remove {
+            container_id: container_id.into(),
+        }
+    }
+
+    fn connect(container_id: &str, alias: &str) -> fake_backend::Operation {
+        fake_backend::Operation::Connect {
+            container_id: container_id.into(),
+            network_attachment: backend::NetworkAttachment {
+                aliases: vec![alias.into()],
+                network_name: "default".into(),
+                ..Default::default()
+            },
+        }
+    }
+
+    fn disconnect(container_id: &str) -> fake_backend::Operation {
+        fake_backend::Operation::Disconnect {
+            container_id: container_id.into(),
+            network_name: "default".into(),
+        }
+    }
+}
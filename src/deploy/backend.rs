@@ -0,0 +1,742 @@
+use super::get_actual_state;
+use super::model;
+use crate::command;
+use crate::docker;
+use crate::docker_compose;
+use crate::engine_api;
+use anyhow::Context;
+use std::collections;
+use std::iter;
+use std::thread;
+use std::time;
+
+pub trait Backend {
+    fn list_containers(
+        &self,
+        service_names: &collections::BTreeSet<String>,
+    ) -> anyhow::Result<model::ActualContainers>;
+
+    fn inspect_container(
+        &self,
+        container_id: &str,
+    ) -> anyhow::Result<Option<model::ActualContainer>>;
+
+    // Makes images of the services available, like `docker compose pull`.
+    fn pull_images(&self, service_names: &collections::BTreeSet<String>) -> anyhow::Result<()>;
+
+    // Like `docker compose up --scale`, starts containers of the service until
+    // there are the given number, then waits for them as configured. A wait
+    // timeout overrides the configured one.
+    fn scale_up(
+        &self,
+        service_name: &str,
+        container_count: u16,
+        wait_timeout: Option<time::Duration>,
+    ) -> anyhow::Result<()>;
+
+    // Like `scale_up`, but only creates the containers without starting them.
+    fn create_containers(&self, service_name: &str, container_count: u16) -> anyhow::Result<()>;
+
+    // Starts the container, then waits for it like `scale_up`.
+    fn start_container(
+        &self,
+        container_id: &str,
+        wait_timeout: Option<time::Duration>,
+    ) -> anyhow::Result<()>;
+
+    // Runs the readiness probe against the container once.
+    fn probe_container(
+        &self,
+        container_id: &str,
+        readiness: &model::Readiness,
+    ) -> anyhow::Result<()>;
+
+    // Runs the command in the running container, like `docker exec`.
+    fn exec_container(&self, container_id: &str, command: &[String]) -> anyhow::Result<()>;
+
+    // A stop timeout overrides the one the container was created with.
+    fn stop_container(
+        &self,
+        container_id: &str,
+        stop_timeout: Option<time::Duration>,
+    ) -> anyhow::Result<()>;
+
+    fn remove_container(&self, container_id: &str) -> anyhow::Result<()>;
+
+    fn inspect_state(&self, container_id: &str) -> anyhow::Result<ContainerState>;
+
+    // Last lines of the container output, stdout and stderr interleaved.
+    fn get_logs(&self, container_id: &str, line_count: u16) -> anyhow::Result<String>;
+
+    fn list_networks(&self, container_id: &str) -> anyhow::Result<Vec<NetworkAttachment>>;
+
+    fn connect_network(
+        &self,
+        container_id: &str,
+        network_attachment: &NetworkAttachment,
+    ) -> anyhow::Result<()>;
+
+    fn disconnect_network(&self, container_id: &str, network_name: &str) -> anyhow::Result<()>;
+
+    // Host address like "127.0.0.1:49153" that the TCP port is published on.
+    fn get_published_address(
+        &self,
+        container_id: &str,
+        container_port: u16,
+    ) -> anyhow::Result<String>;
+}
+
+// Like `State` of `docker inspect`, with fields that Podman may omit defaulted.
+#[derive(Debug, Default, serde::Deserialize)]
+#[serde(default, rename_all = "PascalCase")]
+pub struct ContainerState {
+    pub error: String,
+    pub exit_code: i64,
+    pub health: Option<ContainerHealth>,
+    #[serde(rename = "OOMKilled")]
+    pub oom_killed: bool,
+    pub status: String,
+}
+
+#[derive(Debug, Default, serde::Deserialize)]
+#[serde(default, rename_all = "PascalCase")]
+pub struct ContainerHealth {
+    // Results of the latest health checks, oldest first.
+    pub log: Option<Vec<HealthCheckResult>>,
+    pub status: String,
+}
+
+#[derive(Debug, Default, serde::Deserialize)]
+#[serde(default, rename_all = "PascalCase")]
+pub struct HealthCheckResult {
+    pub exit_code: i64,
+    pub output: String,
+}
+
+// A network that a container is connected to with its endpoint settings there,
+// like DNS aliases or a static IP address, which reconnecting must keep.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct NetworkAttachment {
+    pub aliases: Vec<String>,
+    pub driver_options: collections::BTreeMap<String, String>,
+    pub ipv4_address: Option<String>,
+    pub ipv6_address: Option<String>,
+    pub link_local_ips: Vec<String>,
+    pub links: Vec<String>,
+    pub network_name: String,
+}
+
+// Networks of network modes like "host", which allow no aliases.
+pub const PREDEFINED_NETWORK_NAMES: [&str; 3] = ["bridge", "host", "none"];
+
+pub struct CliBackend<'a> {
+    docker_compose_cli: &'a docker_compose::Cli<'a>,
+    options: CliOptions<'a>,
+    project_name: &'a str,
+}
+
+// Settings shared by the backends of all projects on a host.
+#[derive(Clone, Copy)]
+pub struct CliOptions<'a> {
+    pub docker_cli: &'a docker::Cli<'a>,
+    pub engine_api: Option<&'a engine_api::Client>,
+    pub retry_policy: &'a command::RetryPolicy,
+    pub scale_options: ScaleOptions<'a>,
+}
+
+#[derive(Clone, Copy)]
+pub struct ScaleOptions<'a> {
+    pub no_build: bool,
+    pub no_start: bool,
+    pub pull: Option<&'a str>,
+    pub quiet_pull: bool,
+    pub renew_anon_volumes: bool,
+    pub timeout: Option<&'a str>,
+    pub wait: bool,
+    pub wait_timeout: Option<&'a str>,
+}
+
+impl<'a> CliBackend<'a> {
+    pub fn new(
+        options: CliOptions<'a>,
+        docker_compose_cli: &'a docker_compose::Cli<'a>,
+        project_name: &'a str,
+    ) -> Self {
+        Self {
+            docker_compose_cli,
+            options,
+            project_name,
+        }
+    }
+
+    fn up(
+        &self,
+        service_name: &str,
+        container_count: u16,
+        create_only: bool,
+        wait_timeout_override: Option<time::Duration>,
+    ) -> anyhow::Result<()> {
+        let ScaleOptions {
+            no_build,
+            no_start,
+            pull: _,
+            quiet_pull: _,
+            renew_anon_volumes,
+            timeout,
+            wait,
+            wait_timeout,
+        } = self.options.scale_options;
+        let no_start = no_start || create_only;
+        let scale = format!("{service_name}={container_count}");
+        let wait_arguments = if create_only {
+            vec![]
+        } else {
+            get_wait_arguments(wait, wait_timeout, wait_timeout_override)
+        };
+
+        // Scaling to an absolute count converges, so repeating it is safe.
+        command::retry_safe(self.options.retry_policy, |stderr| {
+            command::status_ok(
+                self.docker_compose_cli
+                    .command()
+                    .args(["up", "--detach"])
+                    .args(no_build.then_some("--no-build").iter())
+                    .args(["--no-deps", "--no-recreate"])
+                    .args(no_start.then_some("--no-start").iter())
+                    // Images have been pulled before already.
+                    .args(["--pull", "never"])
+                    .args(renew_anon_volumes.then_some("--renew-anon-volumes").iter())
+                    .args(["--scale", &scale])
+                    .args(timeout.iter().flat_map(|timeout| ["--timeout", timeout]))
+                    .args(&wait_arguments)
+                    .args(["--", service_name]),
+                stderr,
+            )
+        })
+    }
+
+    // Polls like `docker compose up --wait` until the container is running and
+    // healthy, if it has a healthcheck.
+    fn wait_container(
+        &self,
+        container_id: &str,
+        wait_timeout: Option<time::Duration>,
+    ) -> anyhow::Result<()> {
+        let start = time::Instant::now();
+
+        loop {
+            let state = command::retry_safe(self.options.retry_policy, |stderr| {
+                command::stdout_utf8(
+                    self.options.docker_cli.command().args([
+                        "inspect",
+                        "--format",
+                        "{{.State.Status}} {{if .State.Health}}{{.State.Health.Status}}{{end}}",
+                        "--",
+                        container_id,
+                    ]),
+                    stderr,
+                )
+            })?;
+
+            match state.split_whitespace().collect::<Vec<_>>()[..] {
+                ["running"] | ["running", "healthy"] => break Ok(()),
+                ["running", "unhealthy"] => anyhow::bail!("Container is unhealthy"),
+                ["running", ..] | ["created"] | ["restarting", ..] => {}
+                _ => anyhow::bail!("Container is not running: {}", state.trim()),
+            }
+
+            if wait_timeout.is_some_and(|wait_timeout| start.elapsed() >= wait_timeout) {
+                anyhow::bail!("Timed out waiting for container");
+            }
+            thread::sleep(time::Duration::from_secs(1));
+        }
+    }
+
+    // Runs the probe tool in the container if it has the tool, which is cheaper
+    // than a helper container per attempt. Otherwise, a helper container of the
+    // given image shares the network namespace of the container.
+    fn run_probe_tool(
+        &self,
+        container_id: &str,
+        image: &str,
+        arguments: &[&str],
+        timeout: time::Duration,
+    ) -> anyhow::Result<()> {
+        let result = command::status_ok_within(
+            self.options
+                .docker_cli
+                .command()
+                .args(["exec", "--", container_id, "sh", "-c"])
+                .args([r#"command -v "$0" >/dev/null || exit 127; exec "$0" "$@""#])
+                .args(arguments),
+            timeout,
+        );
+        if !is_command_missing(&result) {
+            return result;
+        }
+
+        let network = format!("container:{container_id}");
+        command::status_ok_within(
+            self.options
+                .docker_cli
+                .command()
+                .args(["run", "--rm", "--network", &network, "--", image])
+                .args(arguments),
+            timeout + HELPER_CONTAINER_ALLOWANCE,
+        )
+    }
+}
+
+impl Backend for CliBackend<'_> {
+    fn list_containers(
+        &self,
+        service_names: &collections::BTreeSet<String>,
+    ) -> anyhow::Result<model::ActualContainers> {
+        get_actual_state::go(
+            service_names,
+            self.project_name,
+            self.options.docker_cli,
+            self.options.engine_api,
+            self.options.retry_policy,
+        )
+    }
+
+    fn inspect_container(
+        &self,
+        container_id: &str,
+    ) -> anyhow::Result<Option<model::ActualContainer>> {
+        command::retry_safe(self.options.retry_policy, |stderr| {
+            get_actual_state::inspect(
+                container_id,
+                self.options.docker_cli,
+                self.options.engine_api,
+                stderr,
+            )
+        })
+    }
+
+    fn pull_images(&self, service_names: &collections::BTreeSet<String>) -> anyhow::Result<()> {
+        let ScaleOptions {
+            pull, quiet_pull, ..
+        } = self.options.scale_options;
+
+        if pull == Some("never") {
+            return Ok(());
+        }
+
+        command::retry_safe(self.options.retry_policy, |stderr| {
+            command::status_ok(
+                self.docker_compose_cli
+                    .command()
+                    .args(get_pull_arguments(pull, quiet_pull))
+                    .arg("--")
+                    .args(service_names),
+                stderr,
+            )
+        })
+    }
+
+    fn scale_up(
+        &self,
+        service_name: &str,
+        container_count: u16,
+        wait_timeout: Option<time::Duration>,
+    ) -> anyhow::Result<()> {
+        self.up(service_name, container_count, false, wait_timeout)
+    }
+
+    fn create_containers(&self, service_name: &str, container_count: u16) -> anyhow::Result<()> {
+        self.up(service_name, container_count, true, None)
+    }
+
+    fn start_container(
+        &self,
+        container_id: &str,
+        wait_timeout_override: Option<time::Duration>,
+    ) -> anyhow::Result<()> {
+        let ScaleOptions {
+            wait, wait_timeout, ..
+        } = self.options.scale_options;
+
+        command::retry_safe(self.options.retry_policy, |stderr| {
+            command::status_ok(
+                self.options
+                    .docker_cli
+                    .command()
+                    .args(["start", "--", container_id]),
+                stderr,
+            )
+        })?;
+
+        // As in `up`, a wait timeout of the service implies waiting.
+        if wait || wait_timeout_override.is_some() {
+            let wait_timeout = match wait_timeout_override {
+                None => wait_timeout
+                    .map(|wait_timeout| wait_timeout.parse().map(time::Duration::from_secs))
+                    .transpose()
+                    .with_context(|| format!("Invalid wait timeout: {wait_timeout:?}"))?,
+                Some(wait_timeout) => Some(wait_timeout),
+            };
+            self.wait_container(container_id, wait_timeout)?;
+        }
+        Ok(())
+    }
+
+    // HTTP and TCP probes run in a helper container sharing the network
+    // namespace of the container, so they reach its ports as on localhost
+    // without the ports being published, and even on remote hosts.
+    fn probe_container(
+        &self,
+        container_id: &str,
+        model::Readiness {
+            image,
+            probe,
+            timeout,
+            ..
+        }: &model::Readiness,
+    ) -> anyhow::Result<()> {
+        let seconds = format_seconds(*timeout);
+
+        match probe {
+            model::Probe::Exec { command: arguments } => command::status_ok_within(
+                self.options
+                    .docker_cli
+                    .command()
+                    .args(["exec", "--", container_id])
+                    .args(arguments),
+                *timeout,
+            ),
+            model::Probe::Http { path, port } => {
+                let url = format!("http://localhost:{port}{path}");
+                self.run_probe_tool(
+                    container_id,
+                    image,
+                    &["wget", "-q", "-T", &seconds, "-O", "/dev/null", &url],
+                    *timeout,
+                )
+            }
+            model::Probe::Tcp { port } => {
+                let port = port.to_string();
+                self.run_probe_tool(
+                    container_id,
+                    image,
+                    &["nc", "-z", "-w", &seconds, "localhost", &port],
+                    *timeout,
+                )
+            }
+        }
+    }
+
+    // Not retried as the command may have side effects.
+    fn exec_container(&self, container_id: &str, command: &[String]) -> anyhow::Result<()> {
+        command::status_ok(
+            self.options
+                .docker_cli
+                .command()
+                .args(["exec", "--", container_id])
+                .args(command),
+            command::Stderr::Inherit,
+        )
+    }
+
+    fn stop_container(
+        &self,
+        container_id: &str,
+        stop_timeout: Option<time::Duration>,
+    ) -> anyhow::Result<()> {
+        let stop_timeout = stop_timeout.map(format_seconds);
+
+        command::retry_safe(self.options.retry_policy, |stderr| {
+            match self.options.engine_api {
+                None => command::status_ok(
+                    self.options
+                        .docker_cli
+                        .command()
+                        .arg("stop")
+                        .args(stop_timeout.iter().flat_map(|timeout| ["--time", timeout]))
+                        .args(["--", container_id]),
+                    stderr,
+                ),
+                Some(engine_api) => {
+                    engine_api.stop_container(container_id, stop_timeout.as_deref())
+                }
+            }
+        })
+    }
+
+    fn remove_container(&self, container_id: &str) -> anyhow::Result<()> {
+        command::retry_unsafe(
+            self.options.retry_policy,
+            |stderr| match self.options.engine_api {
+                None => command::status_ok(
+                    self.options
+                        .docker_cli
+                        .command()
+                        .args(["rm", "--", container_id]),
+                    stderr,
+                ),
+                Some(engine_api) => engine_api.remove_container(container_id),
+            },
+            || {
+                let container = get_actual_state::inspect(
+                    container_id,
+                    self.options.docker_cli,
+                    self.options.engine_api,
+                    command::Stderr::Inherit,
+                )?;
+                Ok(container.is_none())
+            },
+        )
+    }
+
+    fn inspect_state(&self, container_id: &str) -> anyhow::Result<ContainerState> {
+        command::retry_safe(self.options.retry_policy, |stderr| {
+            command::stdout_json(
+                self.options.docker_cli.command().args([
+                    "inspect",
+                    "--format",
+                    "{{json .State}}",
+                    "--",
+                    container_id,
+                ]),
+                stderr,
+            )
+        })
+    }
+
+    fn get_logs(&self, container_id: &str, line_count: u16) -> anyhow::Result<String> {
+        let line_count = line_count.to_string();
+        command::retry_safe(self.options.retry_policy, |_| {
+            command::combined_output_utf8(self.options.docker_cli.command().args([
+                "logs",
+                "--tail",
+                &line_count,
+                "--",
+                container_id,
+            ]))
+        })
+    }
+
+    fn list_networks(&self, container_id: &str) -> anyhow::Result<Vec<NetworkAttachment>> {
+        let networks = command::retry_safe(self.options.retry_policy, |stderr| {
+            command::stdout_json::<collections::BTreeMap<String, Network>>(
+                self.options.docker_cli.command().args([
+                    "inspect",
+                    "--format",
+                    "{{json .NetworkSettings.Networks}}",
+                    "--",
+                    container_id,
+                ]),
+                stderr,
+            )
+        })?;
+
+        Ok(networks
+            .into_iter()
+            .map(|(network_name, network)| {
+                let ipam_config = network.ipam_config.unwrap_or_default();
+                NetworkAttachment {
+                    aliases: network.aliases.unwrap_or_default(),
+                    driver_options: network.driver_opts.unwrap_or_default(),
+                    ipv4_address: ipam_config
+                        .ipv4_address
+                        .filter(|address| !address.is_empty()),
+                    ipv6_address: ipam_config
+                        .ipv6_address
+                        .filter(|address| !address.is_empty()),
+                    link_local_ips: ipam_config.link_local_ips.unwrap_or_default(),
+                    links: network.links.unwrap_or_default(),
+                    network_name,
+                }
+            })
+            .collect())
+    }
+
+    fn connect_network(
+        &self,
+        container_id: &str,
+        NetworkAttachment {
+            aliases,
+            driver_options,
+            ipv4_address,
+            ipv6_address,
+            link_local_ips,
+            links,
+            network_name,
+        }: &NetworkAttachment,
+    ) -> anyhow::Result<()> {
+        command::status_ok(
+            self.options
+                .docker_cli
+                .command()
+                .args(["network", "connect"])
+                .args(aliases.iter().flat_map(|alias| ["--alias", alias]))
+                .args(
+                    driver_options
+                        .iter()
+                        .flat_map(|(key, value)| ["--driver-opt".into(), format!("{key}={value}")]),
+                )
+                .args(ipv4_address.iter().flat_map(|address| ["--ip", address]))
+                .args(ipv6_address.iter().flat_map(|address| ["--ip6", address]))
+                .args(
+                    link_local_ips
+                        .iter()
+                        .flat_map(|address| ["--link-local-ip", address]),
+                )
+                .args(links.iter().flat_map(|link| ["--link", link]))
+                .args(["--", network_name, container_id]),
+            command::Stderr::Inherit,
+        )
+    }
+
+    fn disconnect_network(&self, container_id: &str, network_name: &str) -> anyhow::Result<()> {
+        command::status_ok(
+            self.options.docker_cli.command().args([
+                "network",
+                "disconnect",
+                "--",
+                network_name,
+                container_id,
+            ]),
+            command::Stderr::Inherit,
+        )
+    }
+
+    fn get_published_address(
+        &self,
+        container_id: &str,
+        container_port: u16,
+    ) -> anyhow::Result<String> {
+        let addresses = command::retry_safe(self.options.retry_policy, |stderr| {
+            command::stdout_utf8(
+                self.options.docker_cli.command().args([
+                    "port",
+                    "--",
+                    container_id,
+                    &format!("{container_port}/tcp"),
+                ]),
+                stderr,
+            )
+        })?;
+        let address = addresses
+            .lines()
+            .next()
+            .with_context(|| format!("Port {container_port} is not published"))?;
+
+        // A wildcard address is reachable over the loopback interface.
+        Ok(match address.rsplit_once(':') {
+            Some(("0.0.0.0", port)) => format!("127.0.0.1:{port}"),
+            Some(("[::]", port)) => format!("[::1]:{port}"),
+            _ => address.into(),
+        })
+    }
+}
+
+// Like a shell, `docker exec` exits with 126 or 127 if the command cannot be
+// run, for example without a shell in the container.
+fn is_command_missing(result: &anyhow::Result<()>) -> bool {
+    result.as_ref().is_err_and(|error| {
+        error.chain().any(|cause| {
+            cause
+                .downcast_ref::<command::StatusError>()
+                .is_some_and(|error| matches!(error.status.code(), Some(126 | 127)))
+        })
+    })
+}
+
+// Time for a helper container to start and, on first use, to pull its image.
+const HELPER_CONTAINER_ALLOWANCE: time::Duration = time::Duration::from_secs(60);
+
+// Whole seconds as taken by Docker, rounding up to never cut a timeout short.
+fn format_seconds(duration: time::Duration) -> String {
+    let seconds = duration.as_secs() + u64::from(duration.subsec_nanos() > 0);
+    seconds.to_string()
+}
+
+// Endpoint settings as in `NetworkSettings.Networks` of `docker inspect`.
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Network {
+    aliases: Option<Vec<String>>,
+    driver_opts: Option<collections::BTreeMap<String, String>>,
+    #[serde(rename = "IPAMConfig")]
+    ipam_config: Option<IpamConfig>,
+    links: Option<Vec<String>>,
+}
+
+#[derive(Default, serde::Deserialize)]
+struct IpamConfig {
+    #[serde(rename = "IPv4Address")]
+    ipv4_address: Option<String>,
+    #[serde(rename = "IPv6Address")]
+    ipv6_address: Option<String>,
+    #[serde(rename = "LinkLocalIPs")]
+    link_local_ips: Option<Vec<String>>,
+}
+
+// Services to build instead are skipped. Without `--pull`, each service keeps
+// its own `pull_policy`, like `docker compose up` does.
+fn get_pull_arguments(pull: Option<&str>, quiet_pull: bool) -> Vec<&str> {
+    let mut arguments = vec!["pull", "--ignore-buildable"];
+    arguments.extend(pull.iter().flat_map(|pull| ["--policy", pull]));
+    arguments.extend(quiet_pull.then_some("--quiet"));
+    arguments
+}
+
+// A wait timeout of the service implies waiting, even without `--wait`.
+fn get_wait_arguments(
+    wait: bool,
+    wait_timeout: Option<&str>,
+    wait_timeout_override: Option<time::Duration>,
+) -> Vec<String> {
+    let wait_timeout = match wait_timeout_override {
+        None if !wait => return vec![],
+        None => wait_timeout.map(|wait_timeout| wait_timeout.into()),
+        Some(wait_timeout) => Some(format_seconds(wait_timeout)),
+    };
+    iter::once("--wait".into())
+        .chain(
+            wait_timeout
+                .into_iter()
+                .flat_map(|wait_timeout| ["--wait-timeout".into(), wait_timeout]),
+        )
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case::test_case(None, false => vec!["pull", "--ignore-buildable"]; "service pull policies without pull")]
+    #[test_case::test_case(Some("always"), false => vec!["pull", "--ignore-buildable", "--policy", "always"]; "always")]
+    #[test_case::test_case(Some("missing"), true => vec!["pull", "--ignore-buildable", "--policy", "missing", "--quiet"]; "missing quietly")]
+    fn get_pull_arguments_handles(pull: Option<&str>, quiet_pull: bool) -> Vec<&str> {
+        get_pull_arguments(pull, quiet_pull)
+    }
+
+    #[test_case::test_case(false, Some("60"), None => Vec::<String>::new(); "no wait")]
+    #[test_case::test_case(true, None, None => vec!["--wait"]; "wait")]
+    #[test_case::test_case(true, Some("60"), None => vec!["--wait", "--wait-timeout", "60"]; "wait timeout")]
+    #[test_case::test_case(true, Some("60"), Some(time::Duration::from_millis(1500)) => vec!["--wait", "--wait-timeout", "2"]; "overridden wait timeout")]
+    #[test_case::test_case(false, None, Some(time::Duration::from_secs(5)) => vec!["--wait", "--wait-timeout", "5"]; "service wait timeout only")]
+    fn get_wait_arguments_handles(
+        wait: bool,
+        wait_timeout: Option<&str>,
+        wait_timeout_override: Option<time::Duration>,
+    ) -> Vec<String> {
+        get_wait_arguments(wait, wait_timeout, wait_timeout_override)
+    }
+
+    #[test_case::test_case("true", false; "success")]
+    #[test_case::test_case("exit 1", false; "failure")]
+    #[test_case::test_case("exit 126", true; "not executable")]
+    #[test_case::test_case("exit 127", true; "not found")]
+    fn is_command_missing_handles(script: &str, expected: bool) {
+        let result = command::status_ok_within(
+            std::process::Command::new("sh").args(["-c", script]),
+            time::Duration::from_secs(5),
+        );
+
+        assert_eq!(is_command_missing(&result), expected)
+    }
+}
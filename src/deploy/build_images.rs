@@ -0,0 +1,76 @@
+use super::model;
+use crate::command;
+use crate::docker;
+use crate::docker_compose;
+use crate::log;
+use anyhow::Context;
+use std::collections;
+
+// Builds images of all services with a `build` section, leaving it to the
+// build cache to skip unchanged ones, then records the built image IDs so that
+// containers of other images are recreated.
+pub fn go(
+    In {
+        desired_services,
+        docker_cli,
+        docker_compose_cli,
+        dry_run,
+    }: In,
+) -> anyhow::Result<()> {
+    let service_names = desired_services
+        .iter()
+        .filter(|(_, service_definition)| service_definition.build.is_some())
+        .map(|(service_name, _)| service_name.clone())
+        .collect::<collections::BTreeSet<_>>();
+
+    if service_names.is_empty() {
+        log::debug!("No services to build.");
+        return Ok(());
+    } else if dry_run {
+        // Without building, the resulting image IDs are unknown.
+        log::info!("Would build services {service_names:?}.");
+        return Ok(());
+    }
+
+    // Compose builds the services concurrently.
+    log::info!("Building services {service_names:?}.");
+    command::status_ok(
+        docker_compose_cli
+            .command()
+            .args(["build", "--"])
+            .args(&service_names),
+        command::Stderr::Inherit,
+    )?;
+
+    for service_name in &service_names {
+        let service_definition = desired_services
+            .get_mut(service_name)
+            .context("Unknown service")?;
+        let image_name = service_definition
+            .build
+            .as_ref()
+            .map(|build| build.image_name.as_str())
+            .unwrap_or_default();
+        let image_id = inspect_image(image_name, docker_cli)
+            .with_context(|| format!("Unable to find built image {image_name:?}"))?;
+        service_definition.image_id = Some(image_id);
+    }
+    Ok(())
+}
+
+pub struct In<'a> {
+    pub desired_services: &'a mut model::DesiredServices,
+    pub docker_cli: &'a docker::Cli<'a>,
+    pub docker_compose_cli: &'a docker_compose::Cli<'a>,
+    pub dry_run: bool,
+}
+
+fn inspect_image(image_name: &str, docker_cli: &docker::Cli) -> anyhow::Result<String> {
+    command::stdout_utf8(
+        docker_cli
+            .command()
+            .args(["image", "inspect", "--format", "{{.Id}}", "--", image_name]),
+        command::Stderr::Inherit,
+    )
+    .map(|image_id| image_id.trim().into())
+}
@@ -0,0 +1,116 @@
+use super::backend;
+use anyhow::Context;
+use std::fmt::Write;
+
+const LOG_LINE_COUNT: u16 = 20;
+
+// Describes why a container may have failed, from its state, its latest health
+// check, and its last log lines, so that a deploy log alone tells the cause.
+pub fn go(
+    In {
+        backend,
+        container_id,
+    }: In,
+) -> anyhow::Result<String> {
+    let state = backend
+        .inspect_state(container_id)
+        .context("Unable to inspect state")?;
+    let logs = backend
+        .get_logs(container_id, LOG_LINE_COUNT)
+        .context("Unable to get logs")?;
+
+    Ok(format!(
+        "{}\nLast {LOG_LINE_COUNT} log lines:\n{}",
+        summarize_state(&state),
+        logs.trim_end()
+    ))
+}
+
+pub struct In<'a> {
+    pub backend: &'a dyn backend::Backend,
+    pub container_id: &'a str,
+}
+
+fn summarize_state(
+    backend::ContainerState {
+        error,
+        exit_code,
+        health,
+        oom_killed,
+        status,
+    }: &backend::ContainerState,
+) -> String {
+    let mut summary = format!("State {status:?}");
+    if status == "exited" || *exit_code != 0 {
+        let _ = write!(summary, " with exit code {exit_code}");
+    }
+    if *oom_killed {
+        summary.push_str(", killed for running out of memory");
+    }
+    if !error.is_empty() {
+        let _ = write!(summary, ", error {error:?}");
+    }
+
+    if let Some(backend::ContainerHealth { log, status }) = health {
+        let _ = write!(summary, ", health {status:?}");
+        if let Some(backend::HealthCheckResult { exit_code, output }) =
+            log.iter().flatten().next_back()
+        {
+            let output = output.trim_end();
+            let _ = write!(
+                summary,
+                ", last health check exited with {exit_code}: {output:?}"
+            );
+        }
+    }
+
+    summary
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case::test_case(
+        backend::ContainerState {
+            status: "running".into(),
+            ..Default::default()
+        },
+        r#"State "running""#;
+        "running"
+    )]
+    #[test_case::test_case(
+        backend::ContainerState {
+            exit_code: 137,
+            oom_killed: true,
+            status: "exited".into(),
+            ..Default::default()
+        },
+        r#"State "exited" with exit code 137, killed for running out of memory"#;
+        "out of memory"
+    )]
+    #[test_case::test_case(
+        backend::ContainerState {
+            health: Some(backend::ContainerHealth {
+                log: Some(vec![
+                    backend::HealthCheckResult {
+                        exit_code: 1,
+                        output: "old".into(),
+                    },
+                    backend::HealthCheckResult {
+                        exit_code: 7,
+                        output: "Connection refused\n".into(),
+                    },
+                ]),
+                status: "unhealthy".into(),
+            }),
+            status: "running".into(),
+            ..Default::default()
+        },
+        r#"State "running", health "unhealthy", last health check exited with 7: "Connection refused""#;
+        "unhealthy"
+    )]
+    fn summarize_state_handles(state: backend::ContainerState, expected: &str) {
+        assert_eq!(summarize_state(&state), expected)
+    }
+}
@@ -0,0 +1,358 @@
+use super::metrics;
+use super::model;
+use crate::http;
+use crate::log;
+use anyhow::Context;
+use std::cell;
+use std::fs;
+use std::io;
+use std::io::Write;
+use std::os::fd::AsRawFd;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path;
+use std::time;
+
+const HTTP_TIMEOUT: time::Duration = time::Duration::from_secs(1);
+
+// Destination of lifecycle events as newline-delimited JSON (NDJSON), which
+// also feed metrics. Events are best effort: a failure to send one is logged
+// but does not fail a deploy.
+#[derive(Default)]
+pub struct Stream {
+    metrics: Option<cell::RefCell<metrics::Collector>>,
+    sink: Option<cell::RefCell<Sink>>,
+}
+
+// Where in a rollout events happen.
+#[derive(Clone, Copy)]
+pub struct Scope<'a> {
+    context: Option<&'a str>,
+    project: Option<&'a str>,
+    stream: &'a Stream,
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "snake_case", tag = "event")]
+pub enum Event<'a> {
+    PlanComputed {
+        changes: &'a [model::ServiceContainerChange],
+        duration_seconds: f64,
+    },
+    StepStarted {
+        change: &'a model::ServiceContainerChange,
+    },
+    StepFinished {
+        change: &'a model::ServiceContainerChange,
+        duration_seconds: f64,
+    },
+    StepFailed {
+        change: &'a model::ServiceContainerChange,
+        duration_seconds: f64,
+        error: String,
+    },
+    DeployDone {
+        duration_seconds: f64,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        error: Option<String>,
+    },
+}
+
+enum Sink {
+    File(fs::File),
+    Http { host: String, target: String },
+}
+
+#[derive(serde::Serialize)]
+struct Record<'a> {
+    timestamp: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    context: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    project: Option<&'a str>,
+    #[serde(flatten)]
+    event: &'a Event<'a>,
+}
+
+impl Stream {
+    // A target like "http://localhost:8080/events" is sent a POST request per
+    // event. Any other target is a path of a file or FIFO to append lines to.
+    pub fn open(target: &str) -> anyhow::Result<Self> {
+        let sink = match target.strip_prefix("http://") {
+            None => Sink::File(
+                open_file(target)
+                    .with_context(|| format!("Unable to open event stream {target:?}"))?,
+            ),
+            Some(url) => {
+                let (host, path) = url.split_once('/').unwrap_or((url, ""));
+                Sink::Http {
+                    host: host.into(),
+                    target: format!("/{path}"),
+                }
+            }
+        };
+
+        Ok(Self {
+            metrics: None,
+            sink: Some(cell::RefCell::new(sink)),
+        })
+    }
+
+    pub fn with_metrics_file(self, path: path::PathBuf) -> Self {
+        Self {
+            metrics: Some(cell::RefCell::new(metrics::Collector::new(path))),
+            ..self
+        }
+    }
+
+    pub fn scope<'a>(&'a self, context: Option<&'a str>, project: Option<&'a str>) -> Scope<'a> {
+        Scope {
+            context,
+            project,
+            stream: self,
+        }
+    }
+}
+
+impl Scope<'_> {
+    pub fn send(&self, event: Event) {
+        if let Some(metrics) = &self.stream.metrics {
+            metrics
+                .borrow_mut()
+                .observe(self.context, self.project, &event);
+        }
+
+        if let Some(sink) = &self.stream.sink {
+            let record = Record {
+                timestamp: log::format_timestamp(time::SystemTime::now()),
+                context: self.context,
+                project: self.project,
+                event: &event,
+            };
+
+            if let Err(error) = send_record(&mut sink.borrow_mut(), &record) {
+                log::warn!("Unable to send event: {error:#}");
+            }
+        }
+    }
+}
+
+// Opening a FIFO for writing blocks until there is a reader, so it fails at
+// once instead if there is none. Writes block again afterwards so that lines are
+// never cut off.
+fn open_file(target: &str) -> io::Result<fs::File> {
+    let file = fs::OpenOptions::new()
+        .append(true)
+        .create(true)
+        .custom_flags(libc::O_NONBLOCK)
+        .open(target)?;
+
+    let descriptor = file.as_raw_fd();
+    // SAFETY: The descriptor is open for the lifetime of `file`.
+    let flags = unsafe { libc::fcntl(descriptor, libc::F_GETFL) };
+    if flags == -1
+        || unsafe { libc::fcntl(descriptor, libc::F_SETFL, flags & !libc::O_NONBLOCK) } == -1
+    {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(file)
+}
+
+fn send_record(sink: &mut Sink, record: &Record) -> anyhow::Result<()> {
+    let mut line = serde_json::to_vec(record).context("Unable to serialize event")?;
+    line.push(b'\n');
+
+    match sink {
+        Sink::File(file) => file.write_all(&line).context("Unable to write event"),
+
+        Sink::Http { host, target } => {
+            let response = http::send(
+                http::connect(host, HTTP_TIMEOUT)?,
+                http::Request {
+                    body: Some(http::Body {
+                        content: &line,
+                        content_type: "application/x-ndjson",
+                    }),
+                    host,
+                    method: "POST",
+                    target,
+                },
+            )?;
+
+            if response.is_success() {
+                Ok(())
+            } else {
+                let status = response.status;
+                Err(anyhow::anyhow!("Event endpoint responded with {status}"))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::BufRead;
+    use std::io::Read;
+    use std::net;
+    use std::process;
+    use std::thread;
+
+    #[test]
+    fn handles_file() -> anyhow::Result<()> {
+        let folder = tempfile::tempdir()?;
+        let path = folder.path().join("events.ndjson");
+        let stream = Stream::open(path.to_str().context("Path is not UTF-8")?)?;
+
+        send_events(&stream);
+
+        let events = fs::read_to_string(path)?
+            .lines()
+            .map(|line| Ok(without_timestamp(serde_json::from_str(line)?)))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        assert_eq!(events, expected_events());
+        Ok(())
+    }
+
+    #[test]
+    fn handles_http_endpoint() -> anyhow::Result<()> {
+        let listener = net::TcpListener::bind("127.0.0.1:0")?;
+        let address = listener.local_addr()?;
+        let server = thread::spawn(move || {
+            expected_events()
+                .iter()
+                .map(|_| {
+                    let (connection, _) = listener.accept()?;
+                    receive_event(connection)
+                })
+                .collect::<anyhow::Result<Vec<_>>>()
+        });
+
+        let stream = Stream::open(&format!("http://{address}/events"))?;
+        send_events(&stream);
+
+        let events = server
+            .join()
+            .map_err(|_| anyhow::anyhow!("Server panicked"))??;
+        assert_eq!(events, expected_events());
+        Ok(())
+    }
+
+    #[test]
+    fn ignores_unavailable_http_endpoint() -> anyhow::Result<()> {
+        let address = net::TcpListener::bind("127.0.0.1:0")?.local_addr()?;
+        let stream = Stream::open(&format!("http://{address}/events"))?;
+
+        send_events(&stream);
+
+        Ok(())
+    }
+
+    #[test]
+    fn handles_unresponsive_http_endpoint() -> anyhow::Result<()> {
+        // Accepts connections into the backlog but never responds.
+        let listener = net::TcpListener::bind("127.0.0.1:0")?;
+        let stream = Stream::open(&format!("http://{}/events", listener.local_addr()?))?;
+        let start = time::Instant::now();
+
+        send_events(&stream);
+
+        assert!(start.elapsed() < 4 * HTTP_TIMEOUT);
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_fifo_without_reader() -> anyhow::Result<()> {
+        let folder = tempfile::tempdir()?;
+        let path = folder.path().join("events.fifo");
+        assert!(process::Command::new("mkfifo")
+            .arg(&path)
+            .status()?
+            .success());
+
+        assert!(Stream::open(path.to_str().context("Path is not UTF-8")?).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn handles_fifo_with_reader() -> anyhow::Result<()> {
+        let folder = tempfile::tempdir()?;
+        let path = folder.path().join("events.fifo");
+        assert!(process::Command::new("mkfifo")
+            .arg(&path)
+            .status()?
+            .success());
+        let mut reader = fs::OpenOptions::new()
+            .read(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open(&path)?;
+
+        send_events(&Stream::open(path.to_str().context("Path is not UTF-8")?)?);
+
+        let mut events = String::new();
+        reader.read_to_string(&mut events)?;
+        assert_eq!(events.lines().count(), expected_events().len());
+        Ok(())
+    }
+
+    fn send_events(stream: &Stream) {
+        let change = model::ServiceContainerChange::Add {
+            service_config_hash: "abc".into(),
+            service_name: "greet".into(),
+        };
+        let scope = stream.scope(Some("staging"), None);
+
+        scope.send(Event::StepStarted { change: &change });
+        scope.send(Event::DeployDone {
+            duration_seconds: 1.5,
+            error: None,
+        });
+    }
+
+    fn expected_events() -> Vec<serde_json::Value> {
+        vec![
+            serde_json::json!({
+                "context": "staging",
+                "event": "step_started",
+                "change": {
+                    "kind": "add",
+                    "service_config_hash": "abc",
+                    "service_name": "greet",
+                },
+            }),
+            serde_json::json!({
+                "context": "staging",
+                "event": "deploy_done",
+                "duration_seconds": 1.5,
+            }),
+        ]
+    }
+
+    fn receive_event(connection: net::TcpStream) -> anyhow::Result<serde_json::Value> {
+        let mut reader = std::io::BufReader::new(connection);
+        let mut content_length = 0;
+        loop {
+            let mut header = String::new();
+            reader.read_line(&mut header)?;
+            let header = header.trim_end();
+            if header.is_empty() {
+                break;
+            }
+            if let Some(value) = header.strip_prefix("Content-Length: ") {
+                content_length = value.parse()?;
+            }
+        }
+        let mut body = vec![0; content_length];
+        reader.read_exact(&mut body)?;
+        reader
+            .get_mut()
+            .write_all(b"HTTP/1.1 204 No Content\r\n\r\n")?;
+        Ok(without_timestamp(serde_json::from_slice(&body)?))
+    }
+
+    fn without_timestamp(mut event: serde_json::Value) -> serde_json::Value {
+        if let Some(event) = event.as_object_mut() {
+            event.remove("timestamp");
+        }
+        event
+    }
+}
@@ -0,0 +1,566 @@
+use super::backend;
+use super::model;
+use std::cell;
+use std::collections;
+use std::time;
+
+// In-memory container engine for testing rollouts without Docker. It records
+// the operations applied to it and simulates health checks.
+pub struct FakeBackend {
+    state: cell::RefCell<State>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Operation {
+    Pull {
+        service_names: collections::BTreeSet<String>,
+    },
+    ScaleUp {
+        container_count: u16,
+        service_name: String,
+    },
+    Create {
+        container_count: u16,
+        service_name: String,
+    },
+    Start {
+        container_id: String,
+    },
+    Exec {
+        command: Vec<String>,
+        container_id: String,
+    },
+    Probe {
+        container_id: String,
+    },
+    Stop {
+        container_id: String,
+    },
+    Remove {
+        container_id: String,
+    },
+    Connect {
+        container_id: String,
+        network_attachment: backend::NetworkAttachment,
+    },
+    Disconnect {
+        container_id: String,
+        network_name: String,
+    },
+}
+
+struct State {
+    containers: Vec<Container>,
+    created_container_count: u64,
+    network_modes: collections::BTreeMap<String, String>,
+    operations: Vec<Operation>,
+    service_config_hashes: collections::BTreeMap<String, String>,
+    service_image_ids: collections::BTreeMap<String, String>,
+    static_ips: collections::BTreeMap<String, String>,
+    unavailable_image_services: collections::BTreeSet<String>,
+    unhealthy_services: collections::BTreeSet<String>,
+    unready_services: collections::BTreeSet<String>,
+}
+
+struct Container {
+    container_id: String,
+    image_id: String,
+    is_running: bool,
+    networks: collections::BTreeMap<String, backend::NetworkAttachment>,
+    service_config_hash: String,
+    service_name: String,
+}
+
+impl FakeBackend {
+    // New containers of a service get the config hash and image ID of the
+    // desired service. Containers are connected to a network "default" with the
+    // service name as alias.
+    pub fn new(
+        actual_containers: &model::ActualContainers,
+        desired_services: &model::DesiredServices,
+    ) -> Self {
+        Self {
+            state: cell::RefCell::new(State {
+                containers: actual_containers
+                    .iter()
+                    .map(|container| Container {
+                        container_id: container.container_id.clone(),
+                        image_id: container.image_id.clone(),
+                        is_running: true,
+                        networks: default_networks(&container.service_name, None, None),
+                        service_config_hash: container.service_config_hash.clone(),
+                        service_name: container.service_name.clone(),
+                    })
+                    .collect(),
+                created_container_count: 0,
+                network_modes: collections::BTreeMap::new(),
+                operations: vec![],
+                service_config_hashes: desired_services
+                    .iter()
+                    .map(|(service_name, service_definition)| {
+                        (
+                            service_name.clone(),
+                            service_definition.service_config_hash.clone(),
+                        )
+                    })
+                    .collect(),
+                service_image_ids: desired_services
+                    .iter()
+                    .map(|(service_name, service_definition)| {
+                        let image_id = service_definition.image_id.clone();
+                        (service_name.clone(), image_id.unwrap_or_default())
+                    })
+                    .collect(),
+                static_ips: collections::BTreeMap::new(),
+                unavailable_image_services: collections::BTreeSet::new(),
+                unhealthy_services: collections::BTreeSet::new(),
+                unready_services: collections::BTreeSet::new(),
+            }),
+        }
+    }
+
+    // Containers of the service use the network mode, like "host", instead of
+    // network "default".
+    pub fn with_network_mode(self, service_name: &str, network_mode: &str) -> Self {
+        self.state
+            .borrow_mut()
+            .network_modes
+            .insert(service_name.into(), network_mode.into());
+        self.reset_networks(service_name);
+        self
+    }
+
+    // Containers of the service have the IPv4 address in network "default".
+    pub fn with_static_ip(self, service_name: &str, ipv4_address: &str) -> Self {
+        self.state
+            .borrow_mut()
+            .static_ips
+            .insert(service_name.into(), ipv4_address.into());
+        self.reset_networks(service_name);
+        self
+    }
+
+    // Pulling the image of the service fails.
+    pub fn with_unavailable_image(self, service_name: &str) -> Self {
+        self.state
+            .borrow_mut()
+            .unavailable_image_services
+            .insert(service_name.into());
+        self
+    }
+
+    // New containers of the service never become healthy, so scaling it up
+    // fails like `docker compose up --wait` would.
+    pub fn with_unhealthy_service(self, service_name: &str) -> Self {
+        self.state
+            .borrow_mut()
+            .unhealthy_services
+            .insert(service_name.into());
+        self
+    }
+
+    // Readiness probes of containers of the service always fail.
+    pub fn with_unready_service(self, service_name: &str) -> Self {
+        self.state
+            .borrow_mut()
+            .unready_services
+            .insert(service_name.into());
+        self
+    }
+
+    pub fn operations(&self) -> Vec<Operation> {
+        self.state.borrow().operations.clone()
+    }
+
+    // IDs of running containers that the alias resolves to in network "default".
+    pub fn resolve(&self, alias: &str) -> Vec<String> {
+        self.state
+            .borrow()
+            .containers
+            .iter()
+            .filter(|container| {
+                container.is_running
+                    && container
+                        .networks
+                        .get(DEFAULT_NETWORK)
+                        .is_some_and(|network_attachment| {
+                            network_attachment
+                                .aliases
+                                .iter()
+                                .any(|other| other == alias)
+                        })
+            })
+            .map(|container| container.container_id.clone())
+            .collect()
+    }
+
+    pub fn running_containers(&self) -> model::ActualContainers {
+        self.state
+            .borrow()
+            .containers
+            .iter()
+            .filter(|container| container.is_running)
+            .map(convert_container)
+            .collect()
+    }
+
+    fn create(
+        &self,
+        service_name: &str,
+        container_count: u16,
+        is_running: bool,
+    ) -> anyhow::Result<()> {
+        let mut state = self.state.borrow_mut();
+        let service_config_hash = state
+            .service_config_hashes
+            .get(service_name)
+            .ok_or_else(|| anyhow::anyhow!("No such service: {service_name:?}"))?
+            .clone();
+        let image_id = state.service_image_ids[service_name].clone();
+        let networks = get_networks(&state, service_name);
+        let actual_count = state
+            .containers
+            .iter()
+            .filter(|container| container.service_name == service_name)
+            .count();
+
+        for _ in actual_count..container_count.into() {
+            state.created_container_count += 1;
+            let container_id = format!("{:064x}", state.created_container_count);
+            state.containers.push(Container {
+                container_id,
+                image_id: image_id.clone(),
+                is_running,
+                networks: networks.clone(),
+                service_config_hash: service_config_hash.clone(),
+                service_name: service_name.into(),
+            });
+        }
+        Ok(())
+    }
+
+    fn reset_networks(&self, service_name: &str) {
+        let mut state = self.state.borrow_mut();
+        let networks = get_networks(&state, service_name);
+        for container in &mut state.containers {
+            if container.service_name == service_name {
+                container.networks = networks.clone();
+            }
+        }
+    }
+
+    fn check_health(&self, service_name: &str) -> anyhow::Result<()> {
+        if self
+            .state
+            .borrow()
+            .unhealthy_services
+            .contains(service_name)
+        {
+            Err(anyhow::anyhow!("Service {service_name:?} is unhealthy"))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl backend::Backend for FakeBackend {
+    fn list_containers(
+        &self,
+        service_names: &collections::BTreeSet<String>,
+    ) -> anyhow::Result<model::ActualContainers> {
+        Ok(self
+            .state
+            .borrow()
+            .containers
+            .iter()
+            .filter(|container| {
+                service_names.is_empty() || service_names.contains(&container.service_name)
+            })
+            .map(convert_container)
+            .collect())
+    }
+
+    fn inspect_container(
+        &self,
+        container_id: &str,
+    ) -> anyhow::Result<Option<model::ActualContainer>> {
+        Ok(self
+            .state
+            .borrow()
+            .containers
+            .iter()
+            .find(|container| container.container_id == container_id)
+            .map(convert_container))
+    }
+
+    fn pull_images(&self, service_names: &collections::BTreeSet<String>) -> anyhow::Result<()> {
+        let mut state = self.state.borrow_mut();
+        state.operations.push(Operation::Pull {
+            service_names: service_names.clone(),
+        });
+
+        match service_names
+            .iter()
+            .find(|service_name| state.unavailable_image_services.contains(*service_name))
+        {
+            None => Ok(()),
+            Some(service_name) => Err(anyhow::anyhow!(
+                "Image of service {service_name:?} is unavailable"
+            )),
+        }
+    }
+
+    fn scale_up(
+        &self,
+        service_name: &str,
+        container_count: u16,
+        _wait_timeout: Option<time::Duration>,
+    ) -> anyhow::Result<()> {
+        self.state.borrow_mut().operations.push(Operation::ScaleUp {
+            container_count,
+            service_name: service_name.into(),
+        });
+        self.create(service_name, container_count, true)?;
+        self.check_health(service_name)
+    }
+
+    fn create_containers(&self, service_name: &str, container_count: u16) -> anyhow::Result<()> {
+        self.state.borrow_mut().operations.push(Operation::Create {
+            container_count,
+            service_name: service_name.into(),
+        });
+        self.create(service_name, container_count, false)
+    }
+
+    fn start_container(
+        &self,
+        container_id: &str,
+        _wait_timeout: Option<time::Duration>,
+    ) -> anyhow::Result<()> {
+        let mut state = self.state.borrow_mut();
+        state.operations.push(Operation::Start {
+            container_id: container_id.into(),
+        });
+
+        let container = find_container(&mut state, container_id)?;
+        container.is_running = true;
+        let service_name = container.service_name.clone();
+        drop(state);
+        self.check_health(&service_name)
+    }
+
+    fn probe_container(
+        &self,
+        container_id: &str,
+        _readiness: &model::Readiness,
+    ) -> anyhow::Result<()> {
+        let mut state = self.state.borrow_mut();
+        state.operations.push(Operation::Probe {
+            container_id: container_id.into(),
+        });
+
+        let service_name = find_container(&mut state, container_id)?
+            .service_name
+            .clone();
+        if state.unready_services.contains(&service_name) {
+            anyhow::bail!("Container {container_id} is not ready");
+        }
+        Ok(())
+    }
+
+    fn exec_container(&self, container_id: &str, command: &[String]) -> anyhow::Result<()> {
+        let mut state = self.state.borrow_mut();
+        state.operations.push(Operation::Exec {
+            command: command.into(),
+            container_id: container_id.into(),
+        });
+
+        if !find_container(&mut state, container_id)?.is_running {
+            anyhow::bail!("Container {container_id} is not running");
+        }
+        Ok(())
+    }
+
+    fn stop_container(
+        &self,
+        container_id: &str,
+        _stop_timeout: Option<time::Duration>,
+    ) -> anyhow::Result<()> {
+        let mut state = self.state.borrow_mut();
+        state.operations.push(Operation::Stop {
+            container_id: container_id.into(),
+        });
+
+        find_container(&mut state, container_id)?.is_running = false;
+        Ok(())
+    }
+
+    fn remove_container(&self, container_id: &str) -> anyhow::Result<()> {
+        let mut state = self.state.borrow_mut();
+        state.operations.push(Operation::Remove {
+            container_id: container_id.into(),
+        });
+
+        let index = state
+            .containers
+            .iter()
+            .position(|container| container.container_id == container_id)
+            .ok_or_else(|| anyhow::anyhow!("No such container: {container_id}"))?;
+        if state.containers[index].is_running {
+            anyhow::bail!("Cannot remove running container: {container_id}");
+        }
+        state.containers.remove(index);
+        Ok(())
+    }
+
+    fn inspect_state(&self, container_id: &str) -> anyhow::Result<backend::ContainerState> {
+        let mut state = self.state.borrow_mut();
+        let container = find_container(&mut state, container_id)?;
+        let is_running = container.is_running;
+        let service_name = container.service_name.clone();
+        let is_unhealthy = state.unhealthy_services.contains(&service_name);
+
+        Ok(backend::ContainerState {
+            health: is_unhealthy.then(|| backend::ContainerHealth {
+                log: Some(vec![backend::HealthCheckResult {
+                    exit_code: 1,
+                    output: "Fake health check failed".into(),
+                }]),
+                status: "unhealthy".into(),
+            }),
+            status: if is_running { "running" } else { "created" }.into(),
+            ..Default::default()
+        })
+    }
+
+    // Logs tell the container ID.
+    fn get_logs(&self, container_id: &str, _line_count: u16) -> anyhow::Result<String> {
+        let mut state = self.state.borrow_mut();
+        let container = find_container(&mut state, container_id)?;
+        Ok(format!("Started {}\n", container.container_id))
+    }
+
+    fn list_networks(&self, container_id: &str) -> anyhow::Result<Vec<backend::NetworkAttachment>> {
+        let mut state = self.state.borrow_mut();
+        Ok(find_container(&mut state, container_id)?
+            .networks
+            .values()
+            .cloned()
+            .collect())
+    }
+
+    fn connect_network(
+        &self,
+        container_id: &str,
+        network_attachment: &backend::NetworkAttachment,
+    ) -> anyhow::Result<()> {
+        let mut state = self.state.borrow_mut();
+        state.operations.push(Operation::Connect {
+            container_id: container_id.into(),
+            network_attachment: network_attachment.clone(),
+        });
+
+        let network_name = &network_attachment.network_name;
+        if is_predefined(network_name) {
+            anyhow::bail!("Cannot connect to predefined network {network_name:?}");
+        }
+        let container = find_container(&mut state, container_id)?;
+        if container.networks.contains_key(network_name) {
+            anyhow::bail!("Container {container_id} is already connected to {network_name:?}");
+        }
+        container
+            .networks
+            .insert(network_name.clone(), network_attachment.clone());
+        Ok(())
+    }
+
+    fn disconnect_network(&self, container_id: &str, network_name: &str) -> anyhow::Result<()> {
+        let mut state = self.state.borrow_mut();
+        state.operations.push(Operation::Disconnect {
+            container_id: container_id.into(),
+            network_name: network_name.into(),
+        });
+
+        if is_predefined(network_name) {
+            anyhow::bail!("Cannot disconnect from predefined network {network_name:?}");
+        }
+        find_container(&mut state, container_id)?
+            .networks
+            .remove(network_name)
+            .map(|_| ())
+            .ok_or_else(|| anyhow::anyhow!("Container {container_id} is not in {network_name:?}"))
+    }
+
+    // Ports are published on the container ID as host name.
+    fn get_published_address(
+        &self,
+        container_id: &str,
+        container_port: u16,
+    ) -> anyhow::Result<String> {
+        let mut state = self.state.borrow_mut();
+        let container = find_container(&mut state, container_id)?;
+        Ok(format!("{}:{container_port}", container.container_id))
+    }
+}
+
+const DEFAULT_NETWORK: &str = "default";
+
+fn get_networks(
+    state: &State,
+    service_name: &str,
+) -> collections::BTreeMap<String, backend::NetworkAttachment> {
+    default_networks(
+        service_name,
+        state
+            .network_modes
+            .get(service_name)
+            .map(|mode| mode.as_str()),
+        state.static_ips.get(service_name).cloned(),
+    )
+}
+
+// Like Docker, a network mode like "service:db" means no networks of its own.
+fn default_networks(
+    service_name: &str,
+    network_mode: Option<&str>,
+    ipv4_address: Option<String>,
+) -> collections::BTreeMap<String, backend::NetworkAttachment> {
+    let network_attachment = match network_mode {
+        None => backend::NetworkAttachment {
+            aliases: vec![service_name.into()],
+            ipv4_address,
+            network_name: DEFAULT_NETWORK.into(),
+            ..Default::default()
+        },
+        Some(network_mode) if is_predefined(network_mode) => backend::NetworkAttachment {
+            network_name: network_mode.into(),
+            ..Default::default()
+        },
+        Some(_) => return collections::BTreeMap::new(),
+    };
+    [(network_attachment.network_name.clone(), network_attachment)].into()
+}
+
+fn is_predefined(network_name: &str) -> bool {
+    backend::PREDEFINED_NETWORK_NAMES.contains(&network_name)
+}
+
+fn find_container<'a>(
+    state: &'a mut State,
+    container_id: &str,
+) -> anyhow::Result<&'a mut Container> {
+    state
+        .containers
+        .iter_mut()
+        .find(|container| container.container_id == container_id)
+        .ok_or_else(|| anyhow::anyhow!("No such container: {container_id}"))
+}
+
+fn convert_container(container: &Container) -> model::ActualContainer {
+    model::ActualContainer {
+        container_id: container.container_id.clone(),
+        image_id: container.image_id.clone(),
+        service_config_hash: container.service_config_hash.clone(),
+        service_name: container.service_name.clone(),
+    }
+}
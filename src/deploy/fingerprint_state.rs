@@ -0,0 +1,71 @@
+use super::model;
+use sha2::Digest;
+
+// Identifies the actual containers and the desired services that a plan was
+// made for, so that a saved plan is only applied if neither changed since.
+pub fn go(
+    actual_containers: &model::ActualContainers,
+    desired_services: &model::DesiredServices,
+) -> String {
+    let actual_containers = actual_containers
+        .iter()
+        .map(|container| {
+            serde_json::json!({
+                "container_id": container.container_id,
+                "image_id": container.image_id,
+                "service_config_hash": container.service_config_hash,
+                "service_name": container.service_name,
+            })
+        })
+        .collect::<Vec<_>>();
+    // Besides the config hash, settings and update orders decide the rollout.
+    let state = serde_json::json!({
+        "actual_containers": actual_containers,
+        "desired_services": desired_services,
+    });
+
+    sha2::Sha256::digest(state.to_string())
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time;
+
+    type Change = fn(&mut model::ActualContainer, &mut model::DesiredServiceDefinition);
+
+    #[test_case::test_case(|_, _| {}, true; "same")]
+    #[test_case::test_case(|container, _| container.service_config_hash = "b".into(), false; "changed container")]
+    #[test_case::test_case(|_, service| service.service_config_hash = "b".into(), false; "changed service")]
+    #[test_case::test_case(|_, service| service.replica_count = 2, false; "changed replica count")]
+    #[test_case::test_case(
+        |_, service| service.update_order = model::OperationOrder::StartFirst, false;
+        "changed update order"
+    )]
+    #[test_case::test_case(|_, service| { service.dependencies.insert("y".into()); }, false; "changed dependency")]
+    #[test_case::test_case(
+        |_, service| service.settings.stop_timeout = Some(time::Duration::from_secs(1)), false;
+        "changed setting"
+    )]
+    fn handles(change: Change, expected_same: bool) {
+        let fingerprint = |change: Change| {
+            let mut container = model::ActualContainer {
+                container_id: "0".into(),
+                image_id: "".into(),
+                service_config_hash: "a".into(),
+                service_name: "x".into(),
+            };
+            let mut service_definition = model::DesiredServiceDefinition::new("a");
+            change(&mut container, &mut service_definition);
+            go(
+                &[container].into(),
+                &[("x".into(), service_definition)].into(),
+            )
+        };
+
+        assert_eq!(fingerprint(change) == fingerprint(|_, _| {}), expected_same)
+    }
+}
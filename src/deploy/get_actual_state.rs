@@ -1,41 +1,156 @@
 use super::model;
 use crate::command;
 use crate::docker;
-use crate::docker_compose;
+use crate::engine_api;
+use anyhow::Context;
 use std::collections;
 
 pub fn go(
     service_names: &collections::BTreeSet<String>,
+    project_name: &str,
     docker_cli: &docker::Cli,
-    docker_compose_cli: &docker_compose::Cli,
+    engine_api: Option<&engine_api::Client>,
+    retry_policy: &command::RetryPolicy,
 ) -> anyhow::Result<model::ActualContainers> {
-    let container_ids = command::stdout_utf8(
-        docker_compose_cli
-            .command()
-            .args(["ps", "--all", "--quiet", "--"])
-            .args(service_names),
-    )?;
+    let containers = match engine_api {
+        None => get_with_cli(project_name, docker_cli, retry_policy),
+        Some(engine_api) => get_with_engine_api(project_name, engine_api),
+    }?;
+
+    Ok(containers
+        .into_iter()
+        .filter(|container| {
+            service_names.is_empty() || service_names.contains(&container.service_name)
+        })
+        .collect())
+}
+
+// Reads the output of `docker inspect` for containers, keeping those of the
+// project like they would be listed from the container engine.
+pub fn read(containers: &str, project_name: &str) -> anyhow::Result<model::ActualContainers> {
+    let containers = serde_json::from_str::<Vec<Container>>(containers)?;
+
+    containers
+        .into_iter()
+        .filter(|container| {
+            let labels = &container.config.labels;
+            labels.get(PROJECT_LABEL).map(String::as_str) == Some(project_name)
+                && labels.get(ONE_OFF_LABEL).map(String::as_str) == Some("False")
+        })
+        .map(|container| convert_container(container.id, container.image, container.config.labels))
+        .collect()
+}
+
+// Containers are found by their labels instead of `docker compose ps`, so
+// that containers of services no longer in the Compose file (orphans) are
+// included, too.
+fn get_with_cli(
+    project_name: &str,
+    docker_cli: &docker::Cli,
+    retry_policy: &command::RetryPolicy,
+) -> anyhow::Result<model::ActualContainers> {
+    let container_ids = command::retry_safe(retry_policy, |stderr| {
+        command::stdout_utf8(
+            docker_cli.command().args([
+                "ps",
+                "--all",
+                "--filter",
+                &format!("label={PROJECT_LABEL}={project_name}"),
+                "--filter",
+                &format!("label={ONE_OFF_LABEL}=False"),
+                "--no-trunc",
+                "--quiet",
+            ]),
+            stderr,
+        )
+    })?;
     let container_ids = container_ids.lines().collect::<Vec<_>>();
 
-    let containers = if container_ids.is_empty() {
+    let containers: Vec<Container> = if container_ids.is_empty() {
         vec![]
     } else {
-        command::stdout_json(
-            docker_cli
-                .command()
-                .args(["inspect", "--"])
-                .args(container_ids),
-        )?
+        command::retry_safe(retry_policy, |stderr| {
+            command::stdout_json(
+                docker_cli
+                    .command()
+                    .args(["inspect", "--"])
+                    .args(&container_ids),
+                stderr,
+            )
+        })?
     };
 
-    Ok(containers.into_iter().map(convert_container).collect())
+    containers
+        .into_iter()
+        .map(|container| convert_container(container.id, container.image, container.config.labels))
+        .collect()
 }
 
+pub fn inspect(
+    container_id: &str,
+    docker_cli: &docker::Cli,
+    engine_api: Option<&engine_api::Client>,
+    stderr: command::Stderr,
+) -> anyhow::Result<Option<model::ActualContainer>> {
+    let container = match engine_api {
+        None => {
+            let container_ids = command::stdout_utf8(
+                docker_cli.command().args([
+                    "ps",
+                    "--all",
+                    "--filter",
+                    &format!("id={container_id}"),
+                    "--no-trunc",
+                    "--quiet",
+                ]),
+                stderr,
+            )?;
+
+            if container_ids.trim().is_empty() {
+                None
+            } else {
+                command::stdout_json::<Vec<Container>>(
+                    docker_cli.command().args(["inspect", "--", container_id]),
+                    stderr,
+                )?
+                .pop()
+            }
+        }
+        Some(engine_api) => engine_api.inspect_container::<Container>(container_id)?,
+    };
+
+    container
+        .map(|container| convert_container(container.id, container.image, container.config.labels))
+        .transpose()
+}
+
+// Listing containers with their labels at once saves inspecting them.
+fn get_with_engine_api(
+    project_name: &str,
+    engine_api: &engine_api::Client,
+) -> anyhow::Result<model::ActualContainers> {
+    let containers = engine_api.list_containers(&[
+        format!("{PROJECT_LABEL}={project_name}"),
+        format!("{ONE_OFF_LABEL}=False"),
+    ])?;
+
+    containers
+        .into_iter()
+        .map(|container| convert_container(container.id, container.image_id, container.labels))
+        .collect()
+}
+
+const CONFIG_HASH_LABEL: &str = "com.docker.compose.config-hash";
+const ONE_OFF_LABEL: &str = "com.docker.compose.oneoff";
+const PROJECT_LABEL: &str = "com.docker.compose.project";
+const SERVICE_LABEL: &str = "com.docker.compose.service";
+
 #[derive(serde::Deserialize)]
 #[serde(rename_all = "PascalCase")]
 struct Container {
     config: Config,
     id: String,
+    image: String,
 }
 
 #[derive(serde::Deserialize)]
@@ -44,11 +159,24 @@ struct Config {
     labels: collections::BTreeMap<String, String>,
 }
 
-fn convert_container(container: Container) -> model::ActualContainer {
-    model::ActualContainer {
-        container_id: container.id,
-        // TODO: Consider Podman Compose with `io.podman.compose.config-hash`.
-        service_config_hash: container.config.labels["com.docker.compose.config-hash"].clone(),
-        service_name: container.config.labels["com.docker.compose.service"].clone(),
-    }
+fn convert_container(
+    container_id: String,
+    image_id: String,
+    mut labels: collections::BTreeMap<String, String>,
+) -> anyhow::Result<model::ActualContainer> {
+    let mut take_label = |label| {
+        labels
+            .remove(label)
+            .with_context(|| format!("Container {container_id} has no label {label:?}"))
+    };
+    // TODO: Consider Podman Compose with `io.podman.compose.config-hash`.
+    let service_config_hash = take_label(CONFIG_HASH_LABEL)?;
+    let service_name = take_label(SERVICE_LABEL)?;
+
+    Ok(model::ActualContainer {
+        container_id,
+        image_id,
+        service_config_hash,
+        service_name,
+    })
 }
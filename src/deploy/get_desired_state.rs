@@ -1,36 +1,98 @@
+use super::hash_service_config;
 use super::model;
+use super::ConfigHashSource;
 use crate::command;
 use crate::docker_compose;
+use crate::log;
+use anyhow::Context;
 use std::collections;
+use std::time;
 
 pub fn go(
     service_names: &collections::BTreeSet<String>,
+    config_hash_source: ConfigHashSource,
     docker_compose_cli: &docker_compose::Cli,
-) -> anyhow::Result<model::DesiredServices> {
-    let compose_app_definition = get_compose_app_definition(service_names, docker_compose_cli)?;
-    let service_config_hashes = get_service_config_hashes(docker_compose_cli)?;
+    no_deps: bool,
+    retry_policy: &command::RetryPolicy,
+    strict_start_first: bool,
+) -> anyhow::Result<model::DesiredState> {
+    let compose_app_definition =
+        get_selected_services(service_names, docker_compose_cli, no_deps, retry_policy)?;
+    let service_config_hashes = match config_hash_source {
+        ConfigHashSource::Compose => command::retry_safe(retry_policy, |stderr| {
+            get_service_config_hashes(docker_compose_cli, stderr)
+        })?,
+        ConfigHashSource::Native => hash_service_configs(&compose_app_definition),
+    };
 
-    Ok(compose_app_definition
-        .services
-        .into_iter()
-        .map(|(service_name, service_definition)| {
-            let service_config_hash = service_config_hashes[&service_name].clone();
-            (
-                service_name,
-                convert_service_definition(service_definition, service_config_hash),
-            )
-        })
-        .collect())
+    convert_compose_app_definition(
+        compose_app_definition,
+        service_config_hashes,
+        strict_start_first,
+    )
+}
+
+// Reads the output of `docker compose config --format json` without Compose,
+// hashing service configs natively.
+pub fn read(
+    compose_config: serde_json::Value,
+    strict_start_first: bool,
+) -> anyhow::Result<model::DesiredState> {
+    let compose_app_definition = serde_json::from_value(compose_config)?;
+    let service_config_hashes = hash_service_configs(&compose_app_definition);
+
+    convert_compose_app_definition(
+        compose_app_definition,
+        service_config_hashes,
+        strict_start_first,
+    )
+}
+
+// Lists services of all profiles, which tells orphans apart from services that
+// are merely inactive.
+pub fn get_all_service_names(
+    docker_compose_cli: &docker_compose::Cli,
+    retry_policy: &command::RetryPolicy,
+) -> anyhow::Result<collections::BTreeSet<String>> {
+    let service_names = command::retry_safe(retry_policy, |stderr| {
+        command::stdout_utf8(
+            docker_compose_cli
+                .command_with_all_profiles()
+                .args(["config", "--services"]),
+            stderr,
+        )
+    })?;
+
+    Ok(service_names.lines().map(|line| line.into()).collect())
 }
 
 #[derive(serde::Deserialize)]
 struct ComposeAppDefinition {
-    services: collections::BTreeMap<String, ServiceDefinition>,
+    name: String,
+    // Raw as field order matters for hashing.
+    services: collections::BTreeMap<String, serde_json::Value>,
 }
 
 #[derive(serde::Deserialize)]
 struct ServiceDefinition {
+    // Only whether there is one matters, the build itself is left to Compose.
+    build: Option<serde_json::Value>,
+    container_name: Option<String>,
     deploy: Option<Deploy>,
+    image: Option<String>,
+    mac_address: Option<String>,
+    #[serde(default)]
+    networks: collections::BTreeMap<String, Option<Network>>,
+    #[serde(default)]
+    ports: Vec<docker_compose::Port>,
+    #[serde(rename = "x-wheelsticks")]
+    settings: Option<Settings>,
+}
+
+#[derive(serde::Deserialize)]
+struct Network {
+    ipv4_address: Option<String>,
+    ipv6_address: Option<String>,
 }
 
 #[derive(serde::Deserialize)]
@@ -51,23 +113,182 @@ pub enum OperationOrder {
     StopFirst,
 }
 
+// Our own extension field, where a typo is an error rather than ignored.
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Settings {
+    pre_stop: Option<Command>,
+    readiness: Option<Readiness>,
+    recreate: Option<RecreatePolicy>,
+    stop_timeout: Option<String>,
+    update_order: Option<OperationOrder>,
+    wait_timeout: Option<String>,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Readiness {
+    exec: Option<Command>,
+    http: Option<HttpProbe>,
+    image: Option<String>,
+    interval: Option<String>,
+    retries: Option<u16>,
+    tcp: Option<TcpProbe>,
+    timeout: Option<String>,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct HttpProbe {
+    path: Option<String>,
+    port: u16,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TcpProbe {
+    port: u16,
+}
+
+// Like `command` in Compose, a string is run by a shell.
+#[derive(serde::Deserialize)]
+#[serde(untagged)]
+enum Command {
+    Exec(Vec<String>),
+    Shell(String),
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum RecreatePolicy {
+    Diverged,
+    Never,
+}
+
+// Selects the given services and, unless `no_deps`, the services they depend on
+// transitively. Dependencies are explicitly asked for so that Compose enables
+// them even if in an inactive profile, like `docker compose up` does.
+fn get_selected_services(
+    service_names: &collections::BTreeSet<String>,
+    docker_compose_cli: &docker_compose::Cli,
+    no_deps: bool,
+    retry_policy: &command::RetryPolicy,
+) -> anyhow::Result<ComposeAppDefinition> {
+    let mut requested_names = service_names.clone();
+
+    loop {
+        let mut compose_app_definition = command::retry_safe(retry_policy, |stderr| {
+            get_compose_app_definition(&requested_names, docker_compose_cli, stderr)
+        })?;
+
+        if service_names.is_empty() {
+            break Ok(compose_app_definition);
+        }
+
+        let selected_names = if no_deps {
+            service_names.clone()
+        } else {
+            get_dependency_closure(service_names, &compose_app_definition.services)
+        };
+
+        if selected_names.is_subset(&requested_names) {
+            compose_app_definition
+                .services
+                .retain(|service_name, _| selected_names.contains(service_name));
+            break Ok(compose_app_definition);
+        }
+
+        requested_names.extend(selected_names);
+    }
+}
+
+fn get_dependency_closure(
+    service_names: &collections::BTreeSet<String>,
+    services: &collections::BTreeMap<String, serde_json::Value>,
+) -> collections::BTreeSet<String> {
+    let mut closure = collections::BTreeSet::new();
+    let mut pending = service_names.iter().cloned().collect::<Vec<_>>();
+
+    while let Some(service_name) = pending.pop() {
+        if let Some(service_definition) = services.get(&service_name) {
+            pending.extend(
+                get_dependencies(service_definition)
+                    .into_iter()
+                    .filter(|dependency| !closure.contains(dependency)),
+            );
+        }
+        closure.insert(service_name);
+    }
+
+    closure
+}
+
+// Compose normalizes `depends_on` to a map from service name to conditions.
+fn get_dependencies(service_definition: &serde_json::Value) -> collections::BTreeSet<String> {
+    service_definition
+        .get("depends_on")
+        .and_then(|dependencies| dependencies.as_object())
+        .into_iter()
+        .flat_map(|dependencies| dependencies.keys().cloned())
+        .collect()
+}
+
+// Settings that clash between two containers of a service, which start-first
+// would run at the same time.
+fn get_singleton_settings(service_definition: &ServiceDefinition) -> Vec<String> {
+    let container_name = service_definition
+        .container_name
+        .iter()
+        .map(|container_name| format!("container name {container_name:?}"));
+    let mac_address = service_definition
+        .mac_address
+        .iter()
+        .map(|mac_address| format!("MAC address {mac_address:?}"));
+    let ip_addresses = service_definition
+        .networks
+        .iter()
+        .filter_map(|(network_name, network)| Some((network_name, network.as_ref()?)))
+        .flat_map(|(network_name, network)| {
+            [&network.ipv4_address, &network.ipv6_address]
+                .into_iter()
+                .flatten()
+                .map(move |address| format!("IP address {address:?} in network {network_name:?}"))
+        });
+    let ports = service_definition
+        .ports
+        .iter()
+        .filter_map(|port| port.get_fixed_host_port())
+        .map(|host_port| format!("host port {host_port}"));
+
+    container_name
+        .chain(mac_address)
+        .chain(ip_addresses)
+        .chain(ports)
+        .collect()
+}
+
 fn get_compose_app_definition(
     service_names: &collections::BTreeSet<String>,
     docker_compose_cli: &docker_compose::Cli,
+    stderr: command::Stderr,
 ) -> anyhow::Result<ComposeAppDefinition> {
     command::stdout_json(
         docker_compose_cli
             .command()
             .args(["config", "--format", "json", "--"])
             .args(service_names),
+        stderr,
     )
 }
 
 fn get_service_config_hashes(
     docker_compose_cli: &docker_compose::Cli,
+    stderr: command::Stderr,
 ) -> anyhow::Result<collections::BTreeMap<String, String>> {
-    let service_hashes =
-        command::stdout_table(docker_compose_cli.command().args(["config", "--hash", "*"]))?;
+    let service_hashes = command::stdout_table(
+        docker_compose_cli.command().args(["config", "--hash", "*"]),
+        stderr,
+    )?;
 
     Ok(service_hashes
         .into_iter()
@@ -75,25 +296,363 @@ fn get_service_config_hashes(
         .collect())
 }
 
+fn hash_service_configs(
+    compose_app_definition: &ComposeAppDefinition,
+) -> collections::BTreeMap<String, String> {
+    compose_app_definition
+        .services
+        .iter()
+        .map(|(service_name, service_definition)| {
+            let service_config_hash = hash_service_config::go(service_definition.clone());
+            (service_name.clone(), service_config_hash)
+        })
+        .collect()
+}
+
+fn convert_compose_app_definition(
+    compose_app_definition: ComposeAppDefinition,
+    service_config_hashes: collections::BTreeMap<String, String>,
+    strict_start_first: bool,
+) -> anyhow::Result<model::DesiredState> {
+    let project_name = compose_app_definition.name;
+    let services = compose_app_definition
+        .services
+        .into_iter()
+        .map(|(service_name, service_definition)| {
+            let service_config_hash = service_config_hashes[&service_name].clone();
+            let dependencies = get_dependencies(&service_definition);
+            let service_definition = serde_json::from_value(service_definition)?;
+            let singleton_settings = get_singleton_settings(&service_definition);
+            let mut service_definition = convert_service_definition(
+                service_definition,
+                dependencies,
+                &project_name,
+                service_config_hash,
+                &service_name,
+            )
+            .with_context(|| format!("Unable to read settings of service {service_name:?}"))?;
+
+            if matches!(
+                service_definition.update_order,
+                model::OperationOrder::StartFirst
+            ) && !singleton_settings.is_empty()
+            {
+                let settings = singleton_settings.join(", ");
+                if strict_start_first {
+                    anyhow::bail!(
+                        "Service {service_name:?} is start-first, \
+                        but only one container can have {settings}"
+                    );
+                }
+                log::warn!(
+                    "Updating service {service_name:?} stop-first instead of start-first \
+                    as only one container can have {settings}."
+                );
+                service_definition.update_order = model::OperationOrder::StopFirst;
+            }
+
+            Ok((service_name, service_definition))
+        })
+        .collect::<anyhow::Result<_>>()?;
+
+    Ok(model::DesiredState {
+        project_name,
+        services,
+    })
+}
+
 fn convert_service_definition(
     service_definition: ServiceDefinition,
+    dependencies: collections::BTreeSet<String>,
+    project_name: &str,
     service_config_hash: String,
-) -> model::DesiredServiceDefinition {
-    model::DesiredServiceDefinition {
+    service_name: &str,
+) -> anyhow::Result<model::DesiredServiceDefinition> {
+    let settings = service_definition.settings;
+    let update_order = settings
+        .as_ref()
+        .and_then(|settings| settings.update_order.as_ref());
+
+    Ok(model::DesiredServiceDefinition {
+        build: service_definition.build.map(|_| model::BuildDefinition {
+            // Compose tags built images like this unless an image is given.
+            image_name: service_definition
+                .image
+                .unwrap_or_else(|| format!("{project_name}-{service_name}")),
+        }),
+        dependencies,
+        image_id: None,
         replica_count: service_definition
             .deploy
             .as_ref()
             .and_then(|deploy| deploy.replicas)
             .unwrap_or(1),
         service_config_hash,
-        update_order: match service_definition
+        update_order: match update_order.or(service_definition
             .deploy
-            .and_then(|deploy| deploy.update_config)
-            .and_then(|update_config| update_config.order)
-            .unwrap_or(OperationOrder::StopFirst)
+            .as_ref()
+            .and_then(|deploy| deploy.update_config.as_ref())
+            .and_then(|update_config| update_config.order.as_ref()))
         {
-            OperationOrder::StartFirst => model::OperationOrder::StartFirst,
-            OperationOrder::StopFirst => model::OperationOrder::StopFirst,
+            Some(OperationOrder::StartFirst) => model::OperationOrder::StartFirst,
+            None | Some(OperationOrder::StopFirst) => model::OperationOrder::StopFirst,
+        },
+        settings: match settings {
+            None => model::ServiceSettings::default(),
+            Some(settings) => convert_settings(settings)?,
+        },
+    })
+}
+
+fn convert_settings(
+    Settings {
+        pre_stop,
+        readiness,
+        recreate,
+        stop_timeout,
+        update_order: _,
+        wait_timeout,
+    }: Settings,
+) -> anyhow::Result<model::ServiceSettings> {
+    Ok(model::ServiceSettings {
+        pre_stop: pre_stop.map(convert_command),
+        readiness: readiness.map(convert_readiness).transpose()?,
+        recreate: recreate.map(|recreate| match recreate {
+            RecreatePolicy::Diverged => model::RecreatePolicy::Diverged,
+            RecreatePolicy::Never => model::RecreatePolicy::Never,
+        }),
+        stop_timeout: stop_timeout.as_deref().map(parse_duration).transpose()?,
+        wait_timeout: wait_timeout.as_deref().map(parse_duration).transpose()?,
+    })
+}
+
+fn convert_command(command: Command) -> Vec<String> {
+    match command {
+        Command::Exec(arguments) => arguments,
+        Command::Shell(script) => vec!["/bin/sh".into(), "-c".into(), script],
+    }
+}
+
+fn convert_readiness(
+    Readiness {
+        exec,
+        http,
+        image,
+        interval,
+        retries,
+        tcp,
+        timeout,
+    }: Readiness,
+) -> anyhow::Result<model::Readiness> {
+    let probe = match (exec, http, tcp) {
+        (Some(exec), None, None) => model::Probe::Exec {
+            command: convert_command(exec),
+        },
+        (None, Some(HttpProbe { path, port }), None) => model::Probe::Http {
+            path: path.unwrap_or_else(|| "/".into()),
+            port,
         },
+        (None, None, Some(TcpProbe { port })) => model::Probe::Tcp { port },
+        _ => anyhow::bail!("Readiness needs exactly one of exec, http, or tcp"),
+    };
+
+    Ok(model::Readiness {
+        image: image.unwrap_or_else(|| "busybox:stable".into()),
+        interval: match interval {
+            None => time::Duration::from_secs(1),
+            Some(interval) => parse_duration(&interval)?,
+        },
+        probe,
+        retries: retries.unwrap_or(30),
+        timeout: match timeout {
+            None => time::Duration::from_secs(5),
+            Some(timeout) => parse_duration(&timeout)?,
+        },
+    })
+}
+
+// Parses a duration like "1m30s" in the format of Compose, which supports the
+// units "h", "m", "s", "ms", and "us".
+fn parse_duration(duration: &str) -> anyhow::Result<time::Duration> {
+    let mut rest = duration.trim();
+    let mut total = time::Duration::ZERO;
+    if rest.is_empty() {
+        anyhow::bail!("Empty duration");
+    }
+
+    while !rest.is_empty() {
+        let number_length = rest
+            .find(|character: char| !character.is_ascii_digit() && character != '.')
+            .unwrap_or(rest.len());
+        let (number, unit_and_rest) = rest.split_at(number_length);
+        let unit_length = unit_and_rest
+            .find(|character: char| character.is_ascii_digit() || character == '.')
+            .unwrap_or(unit_and_rest.len());
+        let (unit, next) = unit_and_rest.split_at(unit_length);
+
+        let number = number
+            .parse::<f64>()
+            .with_context(|| format!("Invalid duration: {duration:?}"))?;
+        let unit_seconds = match unit {
+            "h" => 3600.0,
+            "m" => 60.0,
+            "s" => 1.0,
+            "ms" => 1e-3,
+            "us" => 1e-6,
+            _ => anyhow::bail!("Invalid unit {unit:?} in duration {duration:?}"),
+        };
+        total = time::Duration::try_from_secs_f64(number * unit_seconds)
+            .ok()
+            .and_then(|part| total.checked_add(part))
+            .with_context(|| format!("Duration {duration:?} is out of range"))?;
+        rest = next;
+    }
+
+    Ok(total)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case::test_case(serde_json::json!({}), &[]; "none")]
+    #[test_case::test_case(
+        serde_json::json!({"container_name": "proxy"}),
+        &[r#"container name "proxy""#];
+        "container name"
+    )]
+    #[test_case::test_case(
+        serde_json::json!({"ports": [
+            {"host_ip": "127.0.0.1", "published": "8080", "target": 80},
+            {"published": "9000-9010", "target": 90},
+            {"target": 100},
+        ]}),
+        &["host port 127.0.0.1:8080"];
+        "ports"
+    )]
+    #[test_case::test_case(
+        serde_json::json!({"networks": {
+            "default": null,
+            "fixed": {"ipv4_address": "172.16.0.2"},
+        }}),
+        &[r#"IP address "172.16.0.2" in network "fixed""#];
+        "networks"
+    )]
+    fn get_singleton_settings_handles(service_definition: serde_json::Value, expected: &[&str]) {
+        let service_definition = serde_json::from_value(service_definition).unwrap();
+
+        assert_eq!(get_singleton_settings(&service_definition), expected)
+    }
+
+    #[test_case::test_case("90s", Some(90_000); "seconds")]
+    #[test_case::test_case("1m30s", Some(90_000); "minutes and seconds")]
+    #[test_case::test_case("1.5h", Some(5_400_000); "fraction")]
+    #[test_case::test_case("500ms", Some(500); "milliseconds")]
+    #[test_case::test_case("30", None; "no unit")]
+    #[test_case::test_case("", None; "empty")]
+    #[test_case::test_case("99999999999999999999999h", None; "too long")]
+    #[test_case::test_case("5000000000000000h5000000000000000h", None; "too long in sum")]
+    fn parse_duration_handles(duration: &str, expected_milliseconds: Option<u64>) {
+        assert_eq!(
+            parse_duration(duration).ok(),
+            expected_milliseconds.map(time::Duration::from_millis)
+        )
+    }
+
+    #[test]
+    fn convert_service_definition_handles_settings() -> anyhow::Result<()> {
+        let service_definition = serde_json::from_value(serde_json::json!({
+            "deploy": {"update_config": {"order": "stop-first"}},
+            "x-wheelsticks": {
+                "pre_stop": "sleep 5",
+                "recreate": "never",
+                "stop_timeout": "1m",
+                "update_order": "start-first",
+            },
+        }))?;
+
+        let service_definition = convert_service_definition(
+            service_definition,
+            Default::default(),
+            "p",
+            "a".into(),
+            "x",
+        )?;
+
+        assert!(matches!(
+            service_definition.update_order,
+            model::OperationOrder::StartFirst
+        ));
+        assert_eq!(
+            service_definition.settings.pre_stop,
+            Some(vec!["/bin/sh".into(), "-c".into(), "sleep 5".into()])
+        );
+        assert_eq!(
+            service_definition.settings.recreate,
+            Some(model::RecreatePolicy::Never)
+        );
+        assert_eq!(
+            service_definition.settings.stop_timeout,
+            Some(time::Duration::from_secs(60))
+        );
+        assert_eq!(service_definition.settings.wait_timeout, None);
+        Ok(())
+    }
+
+    #[test_case::test_case(
+        serde_json::json!({"http": {"port": 8080}, "retries": 3}),
+        Some((model::Probe::Http { path: "/".into(), port: 8080 }, 3));
+        "http"
+    )]
+    #[test_case::test_case(
+        serde_json::json!({"exec": ["pg_isready"], "interval": "2s"}),
+        Some((model::Probe::Exec { command: vec!["pg_isready".into()] }, 30));
+        "exec"
+    )]
+    #[test_case::test_case(
+        serde_json::json!({"http": {"port": 80}, "tcp": {"port": 80}}),
+        None;
+        "several probes"
+    )]
+    #[test_case::test_case(serde_json::json!({"retries": 3}), None; "no probe")]
+    fn convert_readiness_handles(
+        readiness: serde_json::Value,
+        expected: Option<(model::Probe, u16)>,
+    ) {
+        let readiness = serde_json::from_value(readiness).unwrap();
+
+        assert_eq!(
+            convert_readiness(readiness)
+                .ok()
+                .map(|readiness| (readiness.probe, readiness.retries)),
+            expected
+        )
+    }
+
+    #[test]
+    fn rejects_unknown_setting() {
+        let service_definition = serde_json::from_value::<ServiceDefinition>(serde_json::json!({
+            "x-wheelsticks": {"wait_timout": "1m"},
+        }));
+
+        assert!(service_definition.is_err())
+    }
+
+    #[test_case::test_case(&["x"], &["x", "y", "z"]; "transitive")]
+    #[test_case::test_case(&["y"], &["y", "z"]; "partial")]
+    #[test_case::test_case(&["z"], &["z"]; "none")]
+    #[test_case::test_case(&["w"], &["v", "w"]; "missing from definition")]
+    fn get_dependency_closure_handles(service_names: &[&str], expected: &[&str]) {
+        let services = serde_json::from_value(serde_json::json!({
+            "w": {"depends_on": {"v": {"condition": "service_started"}}},
+            "x": {"depends_on": {"y": {"condition": "service_healthy"}}},
+            "y": {"depends_on": {"z": {}}},
+            "z": {},
+        }))
+        .unwrap();
+        let service_names = service_names.iter().map(|name| (*name).into()).collect();
+        let expected = expected.iter().map(|name| (*name).into()).collect();
+
+        assert_eq!(get_dependency_closure(&service_names, &services), expected)
     }
 }
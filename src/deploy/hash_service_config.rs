@@ -0,0 +1,130 @@
+use sha2::Digest;
+
+// Mirrors `ServiceHash` of Docker Compose. Given the service as output by
+// `docker compose config --format json`, its fields are already in the order
+// that Go's JSON encoding uses, which is why order must be preserved. Extension
+// fields like `x-wheelsticks` are never encoded by Compose.
+//
+// Source: https://github.com/docker/compose/blob/main/pkg/compose/hash.go
+pub fn go(mut service_definition: serde_json::Value) -> String {
+    if let Some(service_definition) = service_definition.as_object_mut() {
+        for field in ["build", "depends_on", "profiles", "pull_policy", "scale"] {
+            service_definition.shift_remove(field);
+        }
+        service_definition.retain(|field, _| !field.starts_with("x-"));
+        if let Some(deploy) = service_definition
+            .get_mut("deploy")
+            .and_then(|deploy| deploy.as_object_mut())
+        {
+            deploy.shift_remove("replicas");
+        }
+    }
+
+    let mut json = String::new();
+    encode_like_go(&service_definition, &mut json);
+
+    sha2::Sha256::digest(json)
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+fn encode_like_go(value: &serde_json::Value, json: &mut String) {
+    match value {
+        serde_json::Value::Array(items) => {
+            json.push('[');
+            for (index, item) in items.iter().enumerate() {
+                if index > 0 {
+                    json.push(',');
+                }
+                encode_like_go(item, json);
+            }
+            json.push(']');
+        }
+        serde_json::Value::Object(fields) => {
+            json.push('{');
+            for (index, (key, value)) in fields.iter().enumerate() {
+                if index > 0 {
+                    json.push(',');
+                }
+                encode_string_like_go(key, json);
+                json.push(':');
+                encode_like_go(value, json);
+            }
+            json.push('}');
+        }
+        serde_json::Value::String(string) => encode_string_like_go(string, json),
+        serde_json::Value::Bool(_) | serde_json::Value::Null | serde_json::Value::Number(_) => {
+            json.push_str(&value.to_string())
+        }
+    }
+}
+
+// Go escapes HTML characters and line/paragraph separators, unlike Serde.
+fn encode_string_like_go(string: &str, json: &mut String) {
+    json.push('"');
+    for character in string.chars() {
+        match character {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            '<' | '>' | '&' | '\u{2028}' | '\u{2029}' | '\0'..='\u{1f}' => {
+                json.push_str(&format!("\\u{:04x}", u32::from(character)))
+            }
+            _ => json.push(character),
+        }
+    }
+    json.push('"');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case::test_case(r#"{}"#, "{}"; "empty")]
+    #[test_case::test_case(
+        r#"{"image": "a", "command": ["b", "c"], "cpus": 0.5, "init": true, "x": null}"#,
+        r#"{"image":"a","command":["b","c"],"cpus":0.5,"init":true,"x":null}"#;
+        "keeps order"
+    )]
+    #[test_case::test_case(
+        r#"{"build": {"context": "."}, "deploy": {"replicas": 2, "resources": {}}, "image": "a", "pull_policy": "always", "scale": 2}"#,
+        r#"{"deploy":{"resources":{}},"image":"a"}"#;
+        "removes fields"
+    )]
+    #[test_case::test_case(
+        r#"{"depends_on": {"db": {"condition": "service_started", "required": true}}, "image": "a", "profiles": ["debug"]}"#,
+        r#"{"image":"a"}"#;
+        "removes dependencies and profiles"
+    )]
+    #[test_case::test_case(
+        r#"{"image": "a", "x-wheelsticks": {"wait_timeout": "30s"}, "x-other": 1}"#,
+        r#"{"image":"a"}"#;
+        "removes extension fields"
+    )]
+    #[test_case::test_case(
+        r#"{"command": "a <b> & \"c\"\n\u0001\u2028"}"#,
+        r#"{"command":"a \u003cb\u003e \u0026 \"c\"\n\u0001\u2028"}"#;
+        "escapes like Go"
+    )]
+    fn handles(service_definition: &str, expected_json: &str) -> anyhow::Result<()> {
+        let expected_hash = sha2::Sha256::digest(expected_json)
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect::<String>();
+
+        assert_eq!(go(serde_json::from_str(service_definition)?), expected_hash);
+        Ok(())
+    }
+
+    #[test]
+    fn hashes_with_sha256() -> anyhow::Result<()> {
+        assert_eq!(
+            go(serde_json::from_str("{}")?),
+            "44136fa355b3678a1146ad16f7e8649e94fb4fc21fe77e8310c060f61caaff8a",
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,373 @@
+use super::events;
+use super::model;
+use crate::log;
+use anyhow::Context;
+use std::collections;
+use std::fmt::Write as _;
+use std::fs;
+use std::io::Write as _;
+use std::os::unix::fs::PermissionsExt;
+use std::path;
+use std::time;
+
+const DEPLOY_FAILURES: &str = "wheelsticks_deploy_failures_total";
+const LAST_SUCCESS_TIMESTAMP: &str = "wheelsticks_last_success_timestamp_seconds";
+
+// Derives metrics from lifecycle events and writes them in the Prometheus text
+// format when a deploy is done, like for the textfile collector of the node
+// exporter. Totals and the last success are carried over from the file.
+pub struct Collector {
+    path: path::PathBuf,
+    services: collections::BTreeMap<ServiceKey, ServiceMetrics>,
+}
+
+#[derive(Eq, Ord, PartialEq, PartialOrd)]
+struct ServiceKey {
+    context: String,
+    project: String,
+    service: String,
+}
+
+#[derive(Default)]
+struct ServiceMetrics {
+    added: u64,
+    failures: u64,
+    kept: u64,
+    removed: u64,
+    rollout_duration_seconds: f64,
+}
+
+struct DeployMetrics {
+    deploy_duration_seconds: f64,
+    deploy_failures: u64,
+    last_success_timestamp: Option<u64>,
+}
+
+impl Collector {
+    pub fn new(path: path::PathBuf) -> Self {
+        Self {
+            path,
+            services: collections::BTreeMap::new(),
+        }
+    }
+
+    pub fn observe(&mut self, context: Option<&str>, project: Option<&str>, event: &events::Event) {
+        match event {
+            events::Event::PlanComputed { .. } | events::Event::StepStarted { .. } => {}
+
+            events::Event::StepFinished {
+                change,
+                duration_seconds,
+            } => {
+                let metrics = self.service_metrics(context, project, change);
+                metrics.rollout_duration_seconds += duration_seconds;
+                match change {
+                    model::ServiceContainerChange::Add { .. } => metrics.added += 1,
+                    model::ServiceContainerChange::Keep { .. } => metrics.kept += 1,
+                    model::ServiceContainerChange::Remove { .. } => metrics.removed += 1,
+                }
+            }
+
+            events::Event::StepFailed {
+                change,
+                duration_seconds,
+                ..
+            } => {
+                let metrics = self.service_metrics(context, project, change);
+                metrics.rollout_duration_seconds += duration_seconds;
+                metrics.failures += 1;
+            }
+
+            events::Event::DeployDone {
+                duration_seconds,
+                error,
+            } => {
+                let result =
+                    self.write(*duration_seconds, error.is_none(), time::SystemTime::now());
+                if let Err(error) = result {
+                    log::warn!("Unable to write metrics: {error:#}");
+                }
+            }
+        }
+    }
+
+    fn service_metrics(
+        &mut self,
+        context: Option<&str>,
+        project: Option<&str>,
+        change: &model::ServiceContainerChange,
+    ) -> &mut ServiceMetrics {
+        let service = match change {
+            model::ServiceContainerChange::Add { service_name, .. }
+            | model::ServiceContainerChange::Keep { service_name, .. }
+            | model::ServiceContainerChange::Remove { service_name, .. } => service_name,
+        };
+
+        self.services
+            .entry(ServiceKey {
+                context: context.unwrap_or_default().into(),
+                project: project.unwrap_or_default().into(),
+                service: service.clone(),
+            })
+            .or_default()
+    }
+
+    fn write(
+        &self,
+        deploy_duration_seconds: f64,
+        is_success: bool,
+        now: time::SystemTime,
+    ) -> anyhow::Result<()> {
+        let previous = match fs::read_to_string(&self.path) {
+            Ok(previous) => previous,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(error) => {
+                return Err(error).with_context(|| format!("Unable to read {:?}", self.path))
+            }
+        };
+        let deploy_failures = read_value(&previous, DEPLOY_FAILURES).unwrap_or_default();
+        let deploy_metrics = DeployMetrics {
+            deploy_duration_seconds,
+            deploy_failures: deploy_failures + u64::from(!is_success),
+            last_success_timestamp: if is_success {
+                Some(now.duration_since(time::UNIX_EPOCH)?.as_secs())
+            } else {
+                read_value(&previous, LAST_SUCCESS_TIMESTAMP)
+            },
+        };
+
+        // The collector may read any time, so the file is replaced atomically. It
+        // typically runs as another user, hence the file is readable by all.
+        let folder = match self.path.parent() {
+            Some(folder) if folder != path::Path::new("") => folder,
+            _ => path::Path::new("."),
+        };
+        let mut file = tempfile::NamedTempFile::new_in(folder)?;
+        file.write_all(self.render(&deploy_metrics).as_bytes())?;
+        file.as_file()
+            .set_permissions(fs::Permissions::from_mode(0o644))?;
+        file.persist(&self.path)
+            .with_context(|| format!("Unable to write {:?}", self.path))?;
+        Ok(())
+    }
+
+    fn render(&self, deploy_metrics: &DeployMetrics) -> String {
+        let mut text = String::new();
+
+        write_header(
+            &mut text,
+            "wheelsticks_service_rollout_duration_seconds",
+            "Time spent changing containers of a service in the last deploy.",
+            "gauge",
+        );
+        for (key, metrics) in &self.services {
+            let labels = format_labels(key, None);
+            let value = metrics.rollout_duration_seconds;
+            writeln!(
+                text,
+                "wheelsticks_service_rollout_duration_seconds{labels} {value}"
+            )
+            .ok();
+        }
+
+        write_header(
+            &mut text,
+            "wheelsticks_service_containers",
+            "Containers of a service by change applied in the last deploy.",
+            "gauge",
+        );
+        for (key, metrics) in &self.services {
+            for (change, value) in [
+                ("add", metrics.added),
+                ("keep", metrics.kept),
+                ("remove", metrics.removed),
+            ] {
+                let labels = format_labels(key, Some(change));
+                writeln!(text, "wheelsticks_service_containers{labels} {value}").ok();
+            }
+        }
+
+        write_header(
+            &mut text,
+            "wheelsticks_service_failures",
+            "Failed changes to containers of a service in the last deploy.",
+            "gauge",
+        );
+        for (key, metrics) in &self.services {
+            let labels = format_labels(key, None);
+            let value = metrics.failures;
+            writeln!(text, "wheelsticks_service_failures{labels} {value}").ok();
+        }
+
+        let DeployMetrics {
+            deploy_duration_seconds,
+            deploy_failures,
+            last_success_timestamp,
+        } = deploy_metrics;
+
+        write_header(
+            &mut text,
+            "wheelsticks_deploy_duration_seconds",
+            "Duration of the last deploy.",
+            "gauge",
+        );
+        writeln!(
+            text,
+            "wheelsticks_deploy_duration_seconds {deploy_duration_seconds}"
+        )
+        .ok();
+
+        write_header(&mut text, DEPLOY_FAILURES, "Failed deploys.", "counter");
+        writeln!(text, "{DEPLOY_FAILURES} {deploy_failures}").ok();
+
+        if let Some(last_success_timestamp) = last_success_timestamp {
+            write_header(
+                &mut text,
+                LAST_SUCCESS_TIMESTAMP,
+                "Unix time of the last successful deploy.",
+                "gauge",
+            );
+            writeln!(text, "{LAST_SUCCESS_TIMESTAMP} {last_success_timestamp}").ok();
+        }
+
+        text
+    }
+}
+
+fn write_header(text: &mut String, name: &str, help: &str, kind: &str) {
+    writeln!(text, "# HELP {name} {help}").ok();
+    writeln!(text, "# TYPE {name} {kind}").ok();
+}
+
+fn format_labels(
+    ServiceKey {
+        context,
+        project,
+        service,
+    }: &ServiceKey,
+    change: Option<&str>,
+) -> String {
+    let mut labels = [
+        ("context", context),
+        ("project", project),
+        ("service", service),
+    ]
+    .into_iter()
+    .map(|(name, value)| format!("{name}=\"{}\"", escape_label_value(value)))
+    .collect::<Vec<_>>();
+    if let Some(change) = change {
+        labels.push(format!("change=\"{change}\""));
+    }
+    format!("{{{}}}", labels.join(","))
+}
+
+fn escape_label_value(value: &str) -> String {
+    value
+        .replace('\\', r"\\")
+        .replace('"', r#"\""#)
+        .replace('\n', r"\n")
+}
+
+fn read_value(text: &str, name: &str) -> Option<u64> {
+    text.lines()
+        .filter_map(|line| line.strip_prefix(name)?.strip_prefix(' '))
+        .find_map(|value| value.trim().parse().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case::test_case(true, "", "0", "1700000000"; "first success")]
+    #[test_case::test_case(
+        false,
+        "wheelsticks_deploy_failures_total 2\nwheelsticks_last_success_timestamp_seconds 1600000000\n",
+        "3",
+        "1600000000";
+        "failure keeps last success"
+    )]
+    #[test_case::test_case(
+        true,
+        "wheelsticks_deploy_failures_total 2\nwheelsticks_last_success_timestamp_seconds 1600000000\n",
+        "2",
+        "1700000000";
+        "success updates last success"
+    )]
+    fn handles(
+        is_success: bool,
+        previous: &str,
+        expected_failures: &str,
+        expected_last_success: &str,
+    ) -> anyhow::Result<()> {
+        let folder = tempfile::tempdir()?;
+        let path = folder.path().join("wheelsticks.prom");
+        fs::write(&path, previous)?;
+        let mut collector = Collector::new(path.clone());
+        let add = model::ServiceContainerChange::Add {
+            service_config_hash: "new".into(),
+            service_name: "greet".into(),
+        };
+        let remove = model::ServiceContainerChange::Remove {
+            container_id: "old-container-id".into(),
+            service_config_hash: "old".into(),
+            service_name: "greet".into(),
+        };
+
+        collector.observe(
+            None,
+            None,
+            &events::Event::StepFinished {
+                change: &add,
+                duration_seconds: 2.5,
+            },
+        );
+        collector.observe(
+            None,
+            None,
+            &events::Event::StepFinished {
+                change: &remove,
+                duration_seconds: 0.5,
+            },
+        );
+        collector.write(
+            4.0,
+            is_success,
+            time::UNIX_EPOCH + time::Duration::from_secs(1_700_000_000),
+        )?;
+
+        let metrics = fs::read_to_string(path)?;
+        let labels = r#"{context="",project="",service="greet""#;
+        for expected in [
+            format!("wheelsticks_service_rollout_duration_seconds{labels}}} 3"),
+            format!(r#"wheelsticks_service_containers{labels},change="add"}} 1"#),
+            format!(r#"wheelsticks_service_containers{labels},change="keep"}} 0"#),
+            format!(r#"wheelsticks_service_containers{labels},change="remove"}} 1"#),
+            format!("wheelsticks_service_failures{labels}}} 0"),
+            "wheelsticks_deploy_duration_seconds 4".into(),
+            format!("wheelsticks_deploy_failures_total {expected_failures}"),
+            format!("wheelsticks_last_success_timestamp_seconds {expected_last_success}"),
+        ] {
+            assert!(
+                metrics.lines().any(|line| line == expected),
+                "{expected:?} not in {metrics:?}",
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn makes_file_readable_by_all() -> anyhow::Result<()> {
+        let folder = tempfile::tempdir()?;
+        let path = folder.path().join("wheelsticks.prom");
+
+        Collector::new(path.clone()).write(1.0, true, time::SystemTime::now())?;
+
+        assert_eq!(fs::metadata(path)?.permissions().mode() & 0o777, 0o644);
+        Ok(())
+    }
+
+    #[test]
+    fn escape_label_value_handles() {
+        assert_eq!(escape_label_value("a\\b\"c\nd"), r#"a\\b\"c\nd"#)
+    }
+}
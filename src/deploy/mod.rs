@@ -1,70 +1,831 @@
-mod apply_changes;
+pub mod apply_changes;
+pub mod backend;
+mod build_images;
+mod diagnose_container;
+pub mod events;
+#[cfg(any(test, feature = "fake-backend"))]
+pub mod fake_backend;
+mod fingerprint_state;
 mod get_actual_state;
 mod get_desired_state;
-mod model;
-mod plan_changes;
+mod hash_service_config;
+mod metrics;
+pub mod model;
+pub mod plan_changes;
+mod probe_availability;
+mod probe_readiness;
+mod pull_images;
+mod select_services;
+pub mod simulate_plan;
+mod verify_state;
+mod write_backends_file;
 
+use super::command;
 use super::docker;
 use super::docker_compose;
+use super::engine_api;
+use super::log;
+use anyhow::Context;
+use backend::Backend;
 use std::collections;
+use std::error;
+use std::fmt;
+use std::path;
+use std::time;
 
 pub fn go(
     In {
+        availability_probe,
         build,
-        docker_cli,
-        docker_compose_cli,
+        config_hash_source,
         dry_run,
+        engine_api,
+        events,
         force_recreate,
+        forwards,
+        hosts,
         no_build,
+        no_deps,
         no_start,
         pull,
         quiet_pull,
         remove_orphans,
         renew_anon_volumes,
-        service_names,
+        retry_policy,
+        saved_plan,
+        service_selection,
+        strict_start_first,
+        switch_aliases,
         timeout,
         wait,
         wait_timeout,
     }: In,
-) -> anyhow::Result<()> {
-    let actual_containers = get_actual_state::go(&service_names, &docker_cli, &docker_compose_cli)?;
-    let desired_services = get_desired_state::go(&service_names, &docker_compose_cli)?;
-    let changes = plan_changes::go(&actual_containers, &desired_services, force_recreate);
+) -> Result<SavedPlan, Error> {
+    let start = time::Instant::now();
+    let host_count = hosts.len();
+    if !forwards.is_empty() {
+        write_backends_file::check_hosts(&hosts).map_err(|source| Error {
+            context: None,
+            project: None,
+            stage: Stage::Plan,
+            source,
+        })?;
+    }
+    let prober = availability_probe
+        .as_ref()
+        .filter(|_| !dry_run)
+        .map(|availability_probe| probe_availability::start(&availability_probe.url))
+        .transpose()
+        .map_err(|source| Error {
+            context: None,
+            project: None,
+            stage: Stage::Probe,
+            source,
+        })?;
+    let mut plan = SavedPlan::default();
+    let result = hosts.into_iter().enumerate().try_for_each(
+        |(
+            host_index,
+            Host {
+                context,
+                docker_cli,
+                projects,
+            },
+        )| {
+            if let Some(context) = context {
+                let host_number = host_index + 1;
+                log::info!("Deploying to context {context:?} ({host_number} of {host_count}).");
+            }
 
-    apply_changes::go(apply_changes::In {
-        actual_containers: &actual_containers,
-        build,
-        changes: &changes,
-        docker_cli: &docker_cli,
-        docker_compose_cli: &docker_compose_cli,
-        dry_run,
-        no_build,
-        no_start,
-        pull: pull.as_deref(),
-        quiet_pull,
-        remove_orphans,
-        renew_anon_volumes,
-        service_names: &service_names,
-        timeout: timeout.as_deref(),
-        wait,
-        wait_timeout: wait_timeout.as_deref(),
-    })
+            let engine_api = if engine_api {
+                connect_engine_api(&docker_cli)
+            } else {
+                None
+            };
+
+            deploy_host(HostDeployment {
+                build,
+                config_hash_source,
+                context,
+                docker_cli: &docker_cli,
+                dry_run,
+                engine_api: engine_api.as_ref(),
+                events: &events,
+                force_recreate,
+                forwards: &forwards,
+                no_build,
+                no_deps,
+                no_start,
+                projects: &projects,
+                pull: pull.as_deref(),
+                quiet_pull,
+                remove_orphans,
+                renew_anon_volumes,
+                retry_policy: &retry_policy,
+                saved_plan: saved_plan.as_ref(),
+                service_selection: &service_selection,
+                strict_start_first,
+                switch_aliases,
+                timeout: timeout.as_deref(),
+                wait,
+                wait_timeout: wait_timeout.as_deref(),
+            })
+            .map(|project_plans| plan.projects.extend(project_plans))
+            .map_err(|error| Error {
+                context: context.map(|context| context.into()),
+                ..error
+            })
+        },
+    );
+    let result = result.map(|()| plan);
+
+    let result = match (prober, availability_probe) {
+        (Some(prober), Some(AvailabilityProbe { error_budget, .. })) => {
+            let report = prober.stop();
+            log_availability(&report);
+            result.and_then(|plan| {
+                check_error_budget(&report, error_budget)
+                    .map(|()| plan)
+                    .map_err(|source| Error {
+                        context: None,
+                        project: None,
+                        stage: Stage::Probe,
+                        source,
+                    })
+            })
+        }
+        _ => result,
+    };
+
+    events.scope(None, None).send(events::Event::DeployDone {
+        duration_seconds: start.elapsed().as_secs_f64(),
+        error: result
+            .as_ref()
+            .err()
+            .map(|error| format!("{error}: {:#}", error.source)),
+    });
+
+    result
 }
 
 pub struct In<'a> {
+    pub availability_probe: Option<AvailabilityProbe>,
     pub build: bool,
-    pub docker_cli: docker::Cli<'a>,
-    pub docker_compose_cli: docker_compose::Cli<'a>,
+    pub config_hash_source: ConfigHashSource,
     pub dry_run: bool,
+    pub engine_api: bool,
+    pub events: events::Stream,
     pub force_recreate: bool,
+    pub forwards: Vec<Forward>,
+    pub hosts: Vec<Host<'a>>,
     pub no_build: bool,
+    pub no_deps: bool,
     pub no_start: bool,
     pub pull: Option<String>,
     pub quiet_pull: bool,
     pub remove_orphans: bool,
     pub renew_anon_volumes: bool,
-    pub service_names: collections::BTreeSet<String>,
+    pub retry_policy: command::RetryPolicy,
+    // Plan to apply instead of planning anew, as long as it was made for the
+    // same actual and desired state.
+    pub saved_plan: Option<SavedPlan>,
+    pub service_selection: ServiceSelection,
+    pub strict_start_first: bool,
+    pub switch_aliases: bool,
     pub timeout: Option<String>,
     pub wait: bool,
     pub wait_timeout: Option<String>,
 }
+
+#[derive(Debug)]
+pub struct Error {
+    pub context: Option<String>,
+    pub project: Option<String>,
+    pub stage: Stage,
+    source: anyhow::Error,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Stage {
+    Build,
+    Plan,
+    Pull,
+    Apply,
+    Verify,
+    Probe,
+}
+
+impl Error {
+    pub fn command_failure(&self) -> Option<&command::StatusError> {
+        self.source
+            .chain()
+            .find_map(|cause| cause.downcast_ref::<command::StatusError>())
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        let stage = match self.stage {
+            Stage::Build => "build images",
+            Stage::Plan => "plan",
+            Stage::Pull => "pull images",
+            Stage::Apply => "apply changes",
+            Stage::Verify => "verify deployment",
+            Stage::Probe => "keep availability within error budget",
+        };
+        write!(formatter, "Unable to {stage}")?;
+        if let Some(project) = &self.project {
+            write!(formatter, " of project {project:?}")?;
+        }
+        if let Some(context) = &self.context {
+            write!(formatter, " on context {context:?}, stopping rollout")?;
+        }
+        Ok(())
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        Some(self.source.as_ref())
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum ConfigHashSource {
+    Compose,
+    Native,
+}
+
+pub struct Host<'a> {
+    pub context: Option<&'a str>,
+    pub docker_cli: docker::Cli<'a>,
+    pub projects: Vec<Project<'a>>,
+}
+
+// Services to consider, where no name patterns mean all services.
+pub struct ServiceSelection {
+    pub exclude_patterns: Vec<String>,
+    pub label_selectors: Vec<String>,
+    pub name_patterns: Vec<String>,
+}
+
+// Keeps a backends file for `wheelsticks forward` listing the host addresses
+// that the given container port of the service's containers is published on.
+pub struct Forward {
+    pub backends_file: path::PathBuf,
+    pub container_port: u16,
+    pub service_name: String,
+}
+
+// Changes per project for `wheelsticks apply` to run exactly, identified by
+// context and project name.
+#[derive(Debug, Default, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct SavedPlan {
+    pub projects: Vec<SavedProjectPlan>,
+}
+
+#[derive(Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct SavedProjectPlan {
+    pub changes: Vec<model::ServiceContainerChange>,
+    pub context: Option<String>,
+    pub fingerprint: String,
+    pub project: Option<String>,
+}
+
+// Requests the URL throughout the deployment, failing it if the share of
+// failed requests in percent exceeds the error budget.
+pub struct AvailabilityProbe {
+    pub error_budget: f64,
+    pub url: String,
+}
+
+pub struct Project<'a> {
+    // Performs container operations instead of the container engine CLI, like
+    // an in-memory fake for testing. The Compose configuration is still read
+    // via Docker Compose.
+    pub backend: Option<&'a dyn Backend>,
+    pub docker_compose_cli: docker_compose::Cli<'a>,
+    pub name: Option<&'a str>,
+}
+
+struct HostDeployment<'a> {
+    build: bool,
+    config_hash_source: ConfigHashSource,
+    context: Option<&'a str>,
+    docker_cli: &'a docker::Cli<'a>,
+    dry_run: bool,
+    engine_api: Option<&'a engine_api::Client>,
+    events: &'a events::Stream,
+    force_recreate: bool,
+    forwards: &'a [Forward],
+    no_build: bool,
+    no_deps: bool,
+    no_start: bool,
+    projects: &'a [Project<'a>],
+    pull: Option<&'a str>,
+    quiet_pull: bool,
+    remove_orphans: bool,
+    renew_anon_volumes: bool,
+    retry_policy: &'a command::RetryPolicy,
+    saved_plan: Option<&'a SavedPlan>,
+    service_selection: &'a ServiceSelection,
+    strict_start_first: bool,
+    switch_aliases: bool,
+    timeout: Option<&'a str>,
+    wait: bool,
+    wait_timeout: Option<&'a str>,
+}
+
+#[derive(Clone, Copy)]
+struct PlanOptions {
+    build: bool,
+    config_hash_source: ConfigHashSource,
+    dry_run: bool,
+    force_recreate: bool,
+    no_deps: bool,
+    remove_orphans: bool,
+    strict_start_first: bool,
+}
+
+struct ChangeCounts {
+    added: usize,
+    kept: usize,
+    removed: usize,
+}
+
+struct ProjectPlan<'a> {
+    actual_containers: model::ActualContainers,
+    all_service_names: collections::BTreeSet<String>,
+    changes: Vec<model::ServiceContainerChange>,
+    desired_state: model::DesiredState,
+    fingerprint: String,
+    project: &'a Project<'a>,
+    service_names: collections::BTreeSet<String>,
+}
+
+fn deploy_host(
+    HostDeployment {
+        build,
+        config_hash_source,
+        context,
+        docker_cli,
+        dry_run,
+        engine_api,
+        events,
+        force_recreate,
+        forwards,
+        no_build,
+        no_deps,
+        no_start,
+        projects,
+        pull,
+        quiet_pull,
+        remove_orphans,
+        renew_anon_volumes,
+        retry_policy,
+        saved_plan,
+        service_selection,
+        strict_start_first,
+        switch_aliases,
+        timeout,
+        wait,
+        wait_timeout,
+    }: HostDeployment,
+) -> Result<Vec<SavedProjectPlan>, Error> {
+    // All projects are planned before any is changed so that a plan can be
+    // reviewed as a whole.
+    let cli_options = backend::CliOptions {
+        docker_cli,
+        engine_api,
+        retry_policy,
+        scale_options: backend::ScaleOptions {
+            no_build,
+            no_start,
+            pull,
+            quiet_pull,
+            renew_anon_volumes,
+            timeout,
+            wait,
+            wait_timeout,
+        },
+    };
+    let plan_options = PlanOptions {
+        build,
+        config_hash_source,
+        dry_run,
+        force_recreate,
+        no_deps,
+        remove_orphans,
+        strict_start_first,
+    };
+    let plans = projects
+        .iter()
+        .filter_map(|project| {
+            let start = time::Instant::now();
+            let mut plan = match plan_project(project, cli_options, plan_options, service_selection)
+            {
+                Err(error) => return Some(Err(error)),
+                Ok(None) => {
+                    let project = summarize_project(project);
+                    log::info!("Skipping {project} as no services match the selection.");
+                    return None;
+                }
+                Ok(Some(plan)) => plan,
+            };
+            if let Some(saved_plan) = saved_plan {
+                plan.changes =
+                    match get_saved_changes(saved_plan, context, project.name, &plan.fingerprint) {
+                        Err(error) => return Some(Err(new_error(project, Stage::Plan, error))),
+                        Ok(changes) => changes.into(),
+                    };
+            }
+
+            events
+                .scope(context, project.name)
+                .send(events::Event::PlanComputed {
+                    changes: &plan.changes,
+                    duration_seconds: start.elapsed().as_secs_f64(),
+                });
+            Some(Ok(plan))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if plans.is_empty() {
+        return Err(Error {
+            context: None,
+            project: None,
+            stage: Stage::Plan,
+            source: anyhow::anyhow!("No services match the selection"),
+        });
+    }
+
+    if let Some(saved_plan) = saved_plan {
+        let saved_count = saved_plan
+            .projects
+            .iter()
+            .filter(|saved_project_plan| saved_project_plan.context.as_deref() == context)
+            .count();
+        if saved_count != plans.len() {
+            return Err(Error {
+                context: context.map(|context| context.into()),
+                project: None,
+                stage: Stage::Plan,
+                source: anyhow::anyhow!(
+                    "Saved plan has {saved_count} projects for this host, not {}",
+                    plans.len()
+                ),
+            });
+        }
+    }
+
+    if plans.len() > 1 {
+        for plan in &plans {
+            let project = summarize_project(plan.project);
+            let ChangeCounts {
+                added,
+                kept,
+                removed,
+            } = count_changes(&plan.changes);
+            log::info!(
+                "Plan for {project}: {added} containers to add, \
+                {kept} to keep, {removed} to remove."
+            );
+        }
+    }
+
+    for plan in &plans {
+        with_backend(
+            plan.project,
+            cli_options,
+            &plan.desired_state.project_name,
+            |backend| {
+                pull_images::go(pull_images::In {
+                    backend,
+                    changes: &plan.changes,
+                    dry_run,
+                })
+            },
+        )
+        .map_err(|error| new_error(plan.project, Stage::Pull, error))?;
+    }
+
+    for ProjectPlan {
+        actual_containers,
+        all_service_names,
+        changes,
+        desired_state,
+        fingerprint: _,
+        project,
+        service_names,
+    } in &plans
+    {
+        with_backend(
+            project,
+            cli_options,
+            &desired_state.project_name,
+            |backend| {
+                apply_changes::go(apply_changes::In {
+                    actual_containers,
+                    backend,
+                    changes,
+                    desired_services: &desired_state.services,
+                    dry_run,
+                    events: events.scope(context, project.name),
+                    forwards,
+                    no_start,
+                    switch_aliases,
+                })
+                .map_err(|error| new_error(project, Stage::Apply, error))?;
+
+                if !dry_run {
+                    list_containers(backend, all_service_names, service_names)
+                        .and_then(|(actual_containers, orphan_containers)| {
+                            let orphan_containers = if remove_orphans {
+                                orphan_containers
+                            } else {
+                                collections::BTreeSet::new()
+                            };
+                            verify_state::go(
+                                &actual_containers,
+                                &desired_state.services,
+                                &orphan_containers,
+                            )
+                        })
+                        .map_err(|error| new_error(project, Stage::Verify, error))?;
+                }
+                Ok(())
+            },
+        )?;
+
+        if !dry_run {
+            let project = summarize_project(project);
+            let ChangeCounts {
+                added,
+                kept,
+                removed,
+            } = count_changes(changes);
+            log::info!(
+                "Deployed {project}: {added} containers added, \
+                {kept} kept, {removed} removed."
+            );
+        }
+    }
+
+    Ok(plans
+        .into_iter()
+        .map(|plan| SavedProjectPlan {
+            changes: plan.changes,
+            context: context.map(|context| context.into()),
+            fingerprint: plan.fingerprint,
+            project: plan.project.name.map(|name| name.into()),
+        })
+        .collect())
+}
+
+// Changes of the saved plan for the project, which are only valid for the
+// state that they were planned for.
+fn get_saved_changes<'a>(
+    saved_plan: &'a SavedPlan,
+    context: Option<&str>,
+    project_name: Option<&str>,
+    fingerprint: &str,
+) -> anyhow::Result<&'a [model::ServiceContainerChange]> {
+    let saved_project_plan = saved_plan
+        .projects
+        .iter()
+        .find(|saved_project_plan| {
+            saved_project_plan.context.as_deref() == context
+                && saved_project_plan.project.as_deref() == project_name
+        })
+        .context("Saved plan has no such project")?;
+
+    if saved_project_plan.fingerprint != fingerprint {
+        anyhow::bail!(
+            "Containers or desired services changed since the plan was saved, plan again"
+        );
+    }
+    Ok(&saved_project_plan.changes)
+}
+
+// Images are built before planning so that rebuilt images are recreated.
+fn plan_project<'a>(
+    project: &'a Project<'a>,
+    cli_options: backend::CliOptions,
+    PlanOptions {
+        build,
+        config_hash_source,
+        dry_run,
+        force_recreate,
+        no_deps,
+        remove_orphans,
+        strict_start_first,
+    }: PlanOptions,
+    service_selection: &ServiceSelection,
+) -> Result<Option<ProjectPlan<'a>>, Error> {
+    let docker_compose_cli = &project.docker_compose_cli;
+    let Some(service_names) = select_services::go(
+        service_selection,
+        docker_compose_cli,
+        cli_options.retry_policy,
+    )
+    .map_err(|error| new_error(project, Stage::Plan, error))?
+    else {
+        return Ok(None);
+    };
+    let mut desired_state = get_desired_state::go(
+        &service_names,
+        config_hash_source,
+        docker_compose_cli,
+        no_deps,
+        cli_options.retry_policy,
+        strict_start_first,
+    )
+    .map_err(|error| new_error(project, Stage::Plan, error))?;
+
+    if build {
+        build_images::go(build_images::In {
+            desired_services: &mut desired_state.services,
+            docker_cli: cli_options.docker_cli,
+            docker_compose_cli,
+            dry_run,
+        })
+        .map_err(|error| new_error(project, Stage::Build, error))?;
+    }
+
+    plan_changes_of_project(
+        project,
+        cli_options,
+        desired_state,
+        force_recreate,
+        remove_orphans,
+    )
+    .map(Some)
+    .map_err(|error| new_error(project, Stage::Plan, error))
+}
+
+fn plan_changes_of_project<'a>(
+    project: &'a Project<'a>,
+    cli_options: backend::CliOptions,
+    desired_state: model::DesiredState,
+    force_recreate: bool,
+    remove_orphans: bool,
+) -> anyhow::Result<ProjectPlan<'a>> {
+    let docker_compose_cli = &project.docker_compose_cli;
+    // Selected services may include dependencies of the given ones. Services
+    // of inactive profiles are left alone like by `docker compose up`.
+    let service_names = desired_state.services.keys().cloned().collect();
+    let all_service_names =
+        get_desired_state::get_all_service_names(docker_compose_cli, cli_options.retry_policy)?;
+    let (mut actual_containers, orphan_containers) = with_backend(
+        project,
+        cli_options,
+        &desired_state.project_name,
+        |backend| list_containers(backend, &all_service_names, &service_names),
+    )?;
+    let mut changes = plan_changes::go(&actual_containers, &desired_state.services, force_recreate);
+
+    if remove_orphans {
+        changes.extend(plan_changes::remove_orphans(&orphan_containers));
+        actual_containers.extend(orphan_containers);
+    } else if !orphan_containers.is_empty() {
+        let orphan_service_names = orphan_containers
+            .iter()
+            .map(|container| container.service_name.as_str())
+            .collect::<collections::BTreeSet<_>>();
+        log::warn!(
+            "Found orphan containers of services {orphan_service_names:?}, \
+            pass --remove-orphans to remove them."
+        );
+    }
+
+    Ok(ProjectPlan {
+        fingerprint: fingerprint_state::go(&actual_containers, &desired_state.services),
+        actual_containers,
+        all_service_names,
+        changes,
+        desired_state,
+        project,
+        service_names,
+    })
+}
+
+// Runs with the backend given for the project, or else the container engine CLI.
+fn with_backend<T>(
+    project: &Project,
+    cli_options: backend::CliOptions,
+    project_name: &str,
+    run: impl FnOnce(&dyn Backend) -> T,
+) -> T {
+    match project.backend {
+        None => run(&backend::CliBackend::new(
+            cli_options,
+            &project.docker_compose_cli,
+            project_name,
+        )),
+        Some(backend) => run(backend),
+    }
+}
+
+// Partitions containers of the project into those of the given services and
+// orphans, ignoring others.
+fn list_containers(
+    backend: &dyn Backend,
+    all_service_names: &collections::BTreeSet<String>,
+    service_names: &collections::BTreeSet<String>,
+) -> anyhow::Result<(model::ActualContainers, model::ActualContainers)> {
+    let (containers, orphan_containers) = backend
+        .list_containers(&collections::BTreeSet::new())?
+        .into_iter()
+        .partition::<model::ActualContainers, _>(|container| {
+            all_service_names.contains(&container.service_name)
+        });
+    let containers = containers
+        .into_iter()
+        .filter(|container| service_names.contains(&container.service_name))
+        .collect();
+
+    Ok((containers, orphan_containers))
+}
+
+fn log_availability(report: &probe_availability::Report) {
+    for outage in report.outages() {
+        if let (Some(first), Some(last)) = (outage.first(), outage.last()) {
+            let start = log::format_timestamp(first.time);
+            let end = log::format_timestamp(last.time);
+            let count = outage.len();
+            let error = &last.error;
+            log::warn!("Probe failed from {start} to {end} ({count} requests): {error}");
+        }
+    }
+
+    let availability = report.availability_percent();
+    let failed = report.failures.len();
+    let total = report.request_count;
+    log::info!(
+        "Observed availability of {availability:.2}% ({failed} of {total} requests failed)."
+    );
+}
+
+fn check_error_budget(
+    report: &probe_availability::Report,
+    error_budget: f64,
+) -> anyhow::Result<()> {
+    let error_percent = 100.0 - report.availability_percent();
+    if error_percent > error_budget {
+        Err(anyhow::anyhow!(
+            "{error_percent:.2}% of probe requests failed, exceeding error budget of \
+            {error_budget}%"
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+fn connect_engine_api(docker_cli: &docker::Cli) -> Option<engine_api::Client> {
+    match docker_cli
+        .endpoint()
+        .and_then(|endpoint| engine_api::Client::connect(&endpoint))
+    {
+        Ok(engine_api) => Some(engine_api),
+        Err(error) => {
+            log::warn!(
+                "Falling back to container engine CLI as Engine API is unavailable: {error:#}"
+            );
+            None
+        }
+    }
+}
+
+fn new_error(project: &Project, stage: Stage, source: anyhow::Error) -> Error {
+    Error {
+        context: None,
+        project: project.name.map(|name| name.into()),
+        stage,
+        source,
+    }
+}
+
+fn summarize_project(project: &Project) -> String {
+    match project.name {
+        None => "project".into(),
+        Some(name) => format!("project {name:?}"),
+    }
+}
+
+fn count_changes(changes: &[model::ServiceContainerChange]) -> ChangeCounts {
+    let mut counts = ChangeCounts {
+        added: 0,
+        kept: 0,
+        removed: 0,
+    };
+
+    for change in changes {
+        match change {
+            model::ServiceContainerChange::Add { .. } => counts.added += 1,
+            model::ServiceContainerChange::Keep { .. } => counts.kept += 1,
+            model::ServiceContainerChange::Remove { .. } => counts.removed += 1,
+        }
+    }
+
+    counts
+}
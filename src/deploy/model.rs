@@ -1,28 +1,91 @@
 use std::collections;
+use std::time;
 
 pub type ActualContainers = collections::BTreeSet<ActualContainer>;
 
-#[derive(Eq, Ord, PartialEq, PartialOrd)]
+#[derive(Debug, Eq, Ord, PartialEq, PartialOrd)]
 pub struct ActualContainer {
     pub container_id: String,
+    pub image_id: String,
     pub service_config_hash: String,
     pub service_name: String,
 }
 
+#[derive(Debug)]
+pub struct DesiredState {
+    pub project_name: String,
+    pub services: DesiredServices,
+}
+
 pub type DesiredServices = collections::BTreeMap<String, DesiredServiceDefinition>;
 
+#[derive(Debug, serde::Serialize)]
 pub struct DesiredServiceDefinition {
+    pub build: Option<BuildDefinition>,
+    pub dependencies: collections::BTreeSet<String>,
+    // Set to the ID of the built image if images are built, which recreates
+    // containers running other images.
+    pub image_id: Option<String>,
     pub replica_count: u16,
     pub service_config_hash: String,
+    pub settings: ServiceSettings,
     pub update_order: OperationOrder,
 }
 
+#[derive(Debug, serde::Serialize)]
+pub struct BuildDefinition {
+    pub image_name: String,
+}
+
+// Per-service settings from the `x-wheelsticks` extension, taking precedence
+// over the corresponding command line options.
+#[derive(Debug, Default, serde::Serialize)]
+pub struct ServiceSettings {
+    // Command run in a container before stopping it.
+    pub pre_stop: Option<Vec<String>>,
+    pub readiness: Option<Readiness>,
+    pub recreate: Option<RecreatePolicy>,
+    pub stop_timeout: Option<time::Duration>,
+    pub wait_timeout: Option<time::Duration>,
+}
+
+// Probe that a new container must pass before the rollout goes on, tried up to
+// `retries` more times with the interval in between.
+#[derive(Debug, serde::Serialize)]
+pub struct Readiness {
+    // Image of the helper container running HTTP and TCP probes if the
+    // container lacks the tools.
+    pub image: String,
+    pub interval: time::Duration,
+    pub probe: Probe,
+    pub retries: u16,
+    pub timeout: time::Duration,
+}
+
+#[derive(Debug, Eq, PartialEq, serde::Serialize)]
+pub enum Probe {
+    Exec { command: Vec<String> },
+    Http { path: String, port: u16 },
+    Tcp { port: u16 },
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, serde::Serialize)]
+pub enum RecreatePolicy {
+    // Recreates containers only if their configuration or image changed, even
+    // with `--force-recreate`.
+    Diverged,
+    // Keeps existing containers even if their configuration or image changed.
+    Never,
+}
+
+#[derive(Debug, serde::Serialize)]
 pub enum OperationOrder {
     StartFirst,
     StopFirst,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "snake_case", tag = "kind")]
 pub enum ServiceContainerChange {
     Add {
         service_config_hash: String,
@@ -39,3 +102,19 @@ pub enum ServiceContainerChange {
         service_name: String,
     },
 }
+
+#[cfg(test)]
+impl DesiredServiceDefinition {
+    // Stop-first service with one replica and default settings.
+    pub fn new(service_config_hash: &str) -> Self {
+        Self {
+            build: None,
+            dependencies: Default::default(),
+            image_id: None,
+            replica_count: 1,
+            service_config_hash: service_config_hash.into(),
+            settings: Default::default(),
+            update_order: OperationOrder::StopFirst,
+        }
+    }
+}
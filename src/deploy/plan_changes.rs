@@ -13,7 +13,7 @@ pub fn go(
         .chain(desired_services.keys())
         .collect::<collections::BTreeSet<_>>();
 
-    let changes = service_names
+    let changes = order_services(service_names, desired_services)
         .into_iter()
         .flat_map(|service_name| {
             let removals = actual_containers
@@ -39,16 +39,87 @@ pub fn go(
         })
         .collect();
 
-    if force_recreate {
-        changes
-    } else {
-        simplify(changes)
+    let outdated_image_container_ids =
+        get_outdated_image_container_ids(actual_containers, desired_services);
+
+    simplify(
+        changes,
+        |service_name, container_id, actual_hash, desired_hash| {
+            let recreate = desired_services
+                .get(service_name)
+                .and_then(|service_definition| service_definition.settings.recreate);
+            match recreate {
+                None if force_recreate => false,
+                None | Some(model::RecreatePolicy::Diverged) => {
+                    actual_hash == desired_hash
+                        && !outdated_image_container_ids.contains(container_id)
+                }
+                Some(model::RecreatePolicy::Never) => true,
+            }
+        },
+    )
+}
+
+// Orphans are containers of services no longer in the Compose file. Removing
+// them last keeps a renamed service available as with start-first.
+pub fn remove_orphans(
+    orphan_containers: &model::ActualContainers,
+) -> Vec<model::ServiceContainerChange> {
+    orphan_containers
+        .iter()
+        .map(service_container_removal)
+        .collect()
+}
+
+// Services come after their dependencies, otherwise in alphabetical order.
+fn order_services<'a>(
+    mut service_names: collections::BTreeSet<&'a String>,
+    desired_services: &model::DesiredServices,
+) -> Vec<&'a String> {
+    let mut ordered_names = vec![];
+
+    while let Some(&service_name) = service_names
+        .iter()
+        .find(|service_name| {
+            desired_services
+                .get(**service_name)
+                .into_iter()
+                .flat_map(|service_definition| &service_definition.dependencies)
+                .all(|dependency| {
+                    dependency == **service_name || !service_names.contains(dependency)
+                })
+        })
+        // Compose rejects dependency cycles, but fall back in case.
+        .or_else(|| service_names.first())
+    {
+        service_names.remove(service_name);
+        ordered_names.push(service_name);
     }
+
+    ordered_names
+}
+
+// Containers running another image than the one built for their service.
+fn get_outdated_image_container_ids<'a>(
+    actual_containers: &'a model::ActualContainers,
+    desired_services: &model::DesiredServices,
+) -> collections::BTreeSet<&'a str> {
+    actual_containers
+        .iter()
+        .filter(|container| {
+            desired_services
+                .get(&container.service_name)
+                .and_then(|service_definition| service_definition.image_id.as_ref())
+                .is_some_and(|image_id| image_id != &container.image_id)
+        })
+        .map(|container| container.container_id.as_str())
+        .collect()
 }
 
 fn service_container_removal(
     model::ActualContainer {
         container_id,
+        image_id: _,
         service_config_hash,
         service_name,
     }: &model::ActualContainer,
@@ -91,7 +162,12 @@ fn alternate<T: IntoIterator<Item = V>, U: IntoIterator<Item = V>, V>(evens: T,
     queue
 }
 
-fn simplify(changes: Vec<model::ServiceContainerChange>) -> Vec<model::ServiceContainerChange> {
+// Turns an addition next to a removal of the same service into keeping the
+// container if `can_keep(service_name, container_id, actual_hash, desired_hash)`.
+fn simplify(
+    changes: Vec<model::ServiceContainerChange>,
+    can_keep: impl Fn(&str, &str, &str, &str) -> bool,
+) -> Vec<model::ServiceContainerChange> {
     let mut changes = collections::VecDeque::from(changes);
     let mut simplified_changes = vec![];
 
@@ -111,11 +187,13 @@ fn simplify(changes: Vec<model::ServiceContainerChange>) -> Vec<model::ServiceCo
                     service_config_hash: b_hash,
                     service_name: b_name,
                 }),
-            ) if a_hash == b_hash && a_name == b_name => model::ServiceContainerChange::Keep {
-                container_id,
-                service_config_hash: b_hash,
-                service_name: b_name,
-            },
+            ) if a_name == b_name && can_keep(&b_name, &container_id, &b_hash, &a_hash) => {
+                model::ServiceContainerChange::Keep {
+                    container_id,
+                    service_config_hash: b_hash,
+                    service_name: b_name,
+                }
+            }
 
             (
                 Some(model::ServiceContainerChange::Remove {
@@ -127,11 +205,13 @@ fn simplify(changes: Vec<model::ServiceContainerChange>) -> Vec<model::ServiceCo
                     service_config_hash: b_hash,
                     service_name: b_name,
                 }),
-            ) if a_hash == b_hash && a_name == b_name => model::ServiceContainerChange::Keep {
-                container_id,
-                service_config_hash: a_hash,
-                service_name: a_name,
-            },
+            ) if a_name == b_name && can_keep(&a_name, &container_id, &a_hash, &b_hash) => {
+                model::ServiceContainerChange::Keep {
+                    container_id,
+                    service_config_hash: a_hash,
+                    service_name: a_name,
+                }
+            }
 
             (Some(a), Some(b)) => {
                 changes.push_front(b);
@@ -247,6 +327,62 @@ mod tests {
         "-Xa₀ +Xd +Yb -Yb₁ +Yb -Yb₂ +Yb -Zc₃ +Ze -Zc₄";
         "force recreate"
     )]
+    #[test_case::test_case(
+        "Xa₀ Ya₁ Za₂",
+        "Xb1∓>Y Yb1∓>Z Zb1∓",
+        false,
+        "-Za₂ +Zb -Ya₁ +Yb -Xa₀ +Xb";
+        "dependencies first"
+    )]
+    #[test_case::test_case(
+        "Xa₀ Ya₁",
+        "Xa1∓>Y Yb1±",
+        false,
+        "+Yb -Ya₁ =Xa₀";
+        "up-to-date dependent"
+    )]
+    #[test_case::test_case(
+        "Xa₀@o",
+        "Xa1∓@o",
+        false,
+        "=Xa₀";
+        "same image"
+    )]
+    #[test_case::test_case(
+        "Xa₀@o",
+        "Xa1∓@n",
+        false,
+        "-Xa₀ +Xa";
+        "rebuilt image"
+    )]
+    #[test_case::test_case(
+        "Xa₀@o",
+        "Xa1∓",
+        false,
+        "=Xa₀";
+        "image not built"
+    )]
+    #[test_case::test_case(
+        "Xa₀",
+        "Xa1∓≠",
+        true,
+        "=Xa₀";
+        "recreate diverged, forced"
+    )]
+    #[test_case::test_case(
+        "Xa₀",
+        "Xb1∓≠",
+        false,
+        "-Xa₀ +Xb";
+        "recreate diverged, unequal hash"
+    )]
+    #[test_case::test_case(
+        "Xa₀@o",
+        "Xb1∓@n≡",
+        true,
+        "=Xa₀";
+        "recreate never"
+    )]
     fn handles(
         actual_containers: &str,
         desired_services: &str,
@@ -255,25 +391,48 @@ mod tests {
     ) -> anyhow::Result<()> {
         let actual_containers = actual_containers
             .split_whitespace()
-            .map(|container| model::ActualContainer {
-                container_id: (&container[2..]).into(),
-                service_config_hash: (&container[1..2]).into(),
-                service_name: (&container[..1]).into(),
+            .map(|container| {
+                let (container, image_id) = container.split_once('@').unwrap_or((container, ""));
+                model::ActualContainer {
+                    container_id: (&container[2..]).into(),
+                    image_id: image_id.into(),
+                    service_config_hash: (&container[1..2]).into(),
+                    service_name: (&container[..1]).into(),
+                }
             })
             .collect();
         let desired_services = desired_services
             .split_whitespace()
             .map(|service| {
+                let (service, recreate) = match service.strip_suffix('≠') {
+                    Some(service) => (service, Some(model::RecreatePolicy::Diverged)),
+                    None => match service.strip_suffix('≡') {
+                        Some(service) => (service, Some(model::RecreatePolicy::Never)),
+                        None => (service, None),
+                    },
+                };
+                let (service, image_id) = match service.split_once('@') {
+                    Some((service, image_id)) => (service, Some(image_id.into())),
+                    None => (service, None),
+                };
+                let (update_order, dependencies) =
+                    service[3..].split_once('>').unwrap_or((&service[3..], ""));
                 Ok((
                     (&service[..1]).into(),
                     model::DesiredServiceDefinition {
+                        dependencies: dependencies.chars().map(|name| name.into()).collect(),
+                        image_id,
                         replica_count: service[2..3].parse()?,
-                        service_config_hash: (&service[1..2]).into(),
-                        update_order: match &service[3..] {
+                        settings: model::ServiceSettings {
+                            recreate,
+                            ..Default::default()
+                        },
+                        update_order: match update_order {
                             "±" => model::OperationOrder::StartFirst,
                             "∓" => model::OperationOrder::StopFirst,
                             update_order => anyhow::bail!("{update_order}"),
                         },
+                        ..model::DesiredServiceDefinition::new(&service[1..2])
                     },
                 ))
             })
@@ -0,0 +1,190 @@
+use crate::http;
+use crate::log;
+use anyhow::Context;
+use std::sync;
+use std::sync::atomic;
+use std::thread;
+use std::time;
+
+const INTERVAL: time::Duration = time::Duration::from_millis(100);
+const TIMEOUT: time::Duration = time::Duration::from_secs(1);
+
+// Requests the URL again and again in the background, like a client would,
+// until stopped. This shows whether a rollout causes downtime.
+pub fn start(url: &str) -> anyhow::Result<Prober> {
+    let (host, path) = url
+        .strip_prefix("http://")
+        .map(|url| url.split_once('/').unwrap_or((url, "")))
+        .with_context(|| format!("Unsupported probe URL {url:?}, expected \"http://…\""))?;
+    let host = host.to_string();
+    let target = format!("/{path}");
+    let is_stopped = sync::Arc::new(atomic::AtomicBool::new(false));
+
+    let worker = thread::spawn({
+        let is_stopped = is_stopped.clone();
+        move || {
+            let mut report = Report {
+                failures: vec![],
+                request_count: 0,
+            };
+
+            while !is_stopped.load(atomic::Ordering::Relaxed) {
+                let start = time::Instant::now();
+                let time = time::SystemTime::now();
+                if let Err(error) = request(&host, &target) {
+                    log::debug!("Availability probe failed: {error:#}");
+                    report.failures.push(Failure {
+                        error: format!("{error:#}"),
+                        request_index: report.request_count,
+                        time,
+                    });
+                }
+                report.request_count += 1;
+                thread::sleep(INTERVAL.saturating_sub(start.elapsed()));
+            }
+
+            report
+        }
+    });
+
+    Ok(Prober { is_stopped, worker })
+}
+
+pub struct Prober {
+    is_stopped: sync::Arc<atomic::AtomicBool>,
+    worker: thread::JoinHandle<Report>,
+}
+
+impl Prober {
+    pub fn stop(self) -> Report {
+        self.is_stopped.store(true, atomic::Ordering::Relaxed);
+        self.worker.join().unwrap_or_else(|_| Report {
+            failures: vec![],
+            request_count: 0,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct Report {
+    pub failures: Vec<Failure>,
+    pub request_count: u64,
+}
+
+#[derive(Debug)]
+pub struct Failure {
+    pub error: String,
+    request_index: u64,
+    pub time: time::SystemTime,
+}
+
+impl Report {
+    // Share of successful requests in percent, 100 without any requests.
+    pub fn availability_percent(&self) -> f64 {
+        if self.request_count == 0 {
+            100.0
+        } else {
+            let success_count = self.request_count - self.failures.len() as u64;
+            100.0 * success_count as f64 / self.request_count as f64
+        }
+    }
+
+    // Runs of consecutive failed requests.
+    pub fn outages(&self) -> Vec<&[Failure]> {
+        self.failures
+            .chunk_by(|a, b| a.request_index + 1 == b.request_index)
+            .collect()
+    }
+}
+
+fn request(host: &str, target: &str) -> anyhow::Result<()> {
+    let response = http::send(
+        http::connect(host, TIMEOUT)?,
+        http::Request {
+            body: None,
+            host,
+            method: "GET",
+            target,
+        },
+    )?;
+
+    if response.is_success() {
+        Ok(())
+    } else {
+        Err(anyhow::anyhow!("Responded with {}", response.status))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use std::io::Write;
+    use std::net;
+
+    #[test]
+    fn handles_available_url() -> anyhow::Result<()> {
+        let listener = net::TcpListener::bind("127.0.0.1:0")?;
+        let address = listener.local_addr()?;
+        thread::spawn(move || -> anyhow::Result<()> {
+            for connection in listener.incoming() {
+                let mut connection = connection?;
+                let mut request = [0; 1024];
+                let _ = connection.read(&mut request)?;
+                connection.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nOK")?;
+            }
+            Ok(())
+        });
+
+        let prober = start(&format!("http://{address}/health"))?;
+        thread::sleep(3 * INTERVAL);
+        let report = prober.stop();
+
+        assert!(report.request_count > 0);
+        assert!(report.failures.is_empty(), "{:?}", report.failures);
+        assert_eq!(report.availability_percent(), 100.0);
+        Ok(())
+    }
+
+    #[test]
+    fn handles_unavailable_url() -> anyhow::Result<()> {
+        let address = net::TcpListener::bind("127.0.0.1:0")?.local_addr()?;
+
+        let prober = start(&format!("http://{address}"))?;
+        thread::sleep(3 * INTERVAL);
+        let report = prober.stop();
+
+        assert!(report.request_count > 0);
+        assert_eq!(report.availability_percent(), 0.0);
+        assert_eq!(report.outages().len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_unsupported_url() {
+        assert!(start("https://example.com").is_err())
+    }
+
+    #[test]
+    fn outages_handles() {
+        let failure = |request_index| Failure {
+            error: "".into(),
+            request_index,
+            time: time::UNIX_EPOCH,
+        };
+        let report = Report {
+            failures: vec![failure(1), failure(2), failure(5)],
+            request_count: 10,
+        };
+
+        assert_eq!(
+            report
+                .outages()
+                .iter()
+                .map(|outage| outage.len())
+                .collect::<Vec<_>>(),
+            [2, 1]
+        );
+        assert_eq!(report.availability_percent(), 70.0);
+    }
+}
@@ -0,0 +1,46 @@
+use super::backend;
+use super::model;
+use crate::log;
+use anyhow::Context;
+use std::thread;
+
+// Probes the new container until it is ready, which must happen before an old
+// container is removed, so a failure stops the rollout.
+pub fn go(
+    In {
+        backend,
+        container_id,
+        readiness,
+    }: In,
+) -> anyhow::Result<()> {
+    let attempts = u32::from(readiness.retries) + 1;
+    let mut attempt = 1;
+
+    loop {
+        match backend.probe_container(container_id, readiness) {
+            Ok(()) => {
+                log::debug!("Container {container_id} is ready after {attempt} attempts.");
+                break Ok(());
+            }
+            Err(error) if attempt < attempts => {
+                log::debug!(
+                    "Readiness probe attempt {attempt} of {attempts} failed for container \
+                    {container_id}: {error:#}"
+                );
+                thread::sleep(readiness.interval);
+                attempt += 1;
+            }
+            Err(error) => {
+                break Err(error).with_context(|| {
+                    format!("Container is not ready after {attempts} readiness probes")
+                })
+            }
+        }
+    }
+}
+
+pub struct In<'a> {
+    pub backend: &'a dyn backend::Backend,
+    pub container_id: &'a str,
+    pub readiness: &'a model::Readiness,
+}
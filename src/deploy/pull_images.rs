@@ -0,0 +1,83 @@
+use super::backend;
+use super::model;
+use crate::log;
+use std::collections;
+
+// Pulls images of services to add before any container is changed, so that an
+// unavailable image aborts a deploy with nothing touched.
+pub fn go(
+    In {
+        backend,
+        changes,
+        dry_run,
+    }: In,
+) -> anyhow::Result<()> {
+    let service_names = changes
+        .iter()
+        .filter_map(|change| match change {
+            model::ServiceContainerChange::Add { service_name, .. } => Some(service_name.clone()),
+            model::ServiceContainerChange::Keep { .. }
+            | model::ServiceContainerChange::Remove { .. } => None,
+        })
+        .collect::<collections::BTreeSet<_>>();
+
+    if service_names.is_empty() {
+        Ok(())
+    } else if dry_run {
+        log::info!("Would pull images of services {service_names:?}.");
+        Ok(())
+    } else {
+        log::info!("Pulling images of services {service_names:?}.");
+        backend.pull_images(&service_names)
+    }
+}
+
+pub struct In<'a> {
+    pub backend: &'a dyn backend::Backend,
+    pub changes: &'a [model::ServiceContainerChange],
+    pub dry_run: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::fake_backend;
+    use super::*;
+
+    #[test_case::test_case(None, true; "available")]
+    #[test_case::test_case(Some("x"), false; "unavailable")]
+    fn handles(unavailable_image_service: Option<&str>, expected_ok: bool) {
+        let desired_services = ["x", "y"]
+            .into_iter()
+            .map(|service_name| {
+                (
+                    service_name.into(),
+                    model::DesiredServiceDefinition::new("new"),
+                )
+            })
+            .collect();
+        let backend = fake_backend::FakeBackend::new(&Default::default(), &desired_services);
+        let backend = match unavailable_image_service {
+            None => backend,
+            Some(service_name) => backend.with_unavailable_image(service_name),
+        };
+        let changes = [model::ServiceContainerChange::Add {
+            service_config_hash: "new".into(),
+            service_name: "x".into(),
+        }];
+
+        let result = go(In {
+            backend: &backend,
+            changes: &changes,
+            dry_run: false,
+        });
+
+        assert_eq!(result.is_ok(), expected_ok);
+        assert_eq!(
+            backend.operations(),
+            [fake_backend::Operation::Pull {
+                service_names: ["x".into()].into(),
+            }],
+        );
+        assert!(backend.running_containers().is_empty());
+    }
+}
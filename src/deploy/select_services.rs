@@ -0,0 +1,199 @@
+use super::ServiceSelection;
+use crate::command;
+use crate::docker_compose;
+use std::collections;
+
+// Resolves glob patterns, exclusions, and label selectors to service names.
+// Without any of these, the given names are kept as is, where no names means
+// all services. Nothing is returned if no service matches.
+pub fn go(
+    ServiceSelection {
+        exclude_patterns,
+        label_selectors,
+        name_patterns,
+    }: &ServiceSelection,
+    docker_compose_cli: &docker_compose::Cli,
+    retry_policy: &command::RetryPolicy,
+) -> anyhow::Result<Option<collections::BTreeSet<String>>> {
+    if exclude_patterns.is_empty()
+        && label_selectors.is_empty()
+        && !name_patterns.iter().any(|pattern| is_glob(pattern))
+    {
+        return Ok(Some(name_patterns.iter().cloned().collect()));
+    }
+
+    let compose_app_definition = command::retry_safe(retry_policy, |stderr| {
+        command::stdout_json::<ComposeAppDefinition>(
+            docker_compose_cli
+                .command()
+                .args(["config", "--format", "json"]),
+            stderr,
+        )
+    })?;
+
+    let service_names = select(
+        &compose_app_definition.services,
+        name_patterns,
+        exclude_patterns,
+        label_selectors,
+    )?;
+
+    Ok((!service_names.is_empty()).then_some(service_names))
+}
+
+#[derive(serde::Deserialize)]
+struct ComposeAppDefinition {
+    services: collections::BTreeMap<String, ServiceDefinition>,
+}
+
+#[derive(serde::Deserialize)]
+struct ServiceDefinition {
+    #[serde(default)]
+    labels: collections::BTreeMap<String, String>,
+}
+
+fn select(
+    services: &collections::BTreeMap<String, ServiceDefinition>,
+    name_patterns: &[String],
+    exclude_patterns: &[String],
+    label_selectors: &[String],
+) -> anyhow::Result<collections::BTreeSet<String>> {
+    if let Some(name) = name_patterns
+        .iter()
+        .find(|pattern| !is_glob(pattern) && !services.contains_key(*pattern))
+    {
+        anyhow::bail!("No such service: {name:?}");
+    }
+
+    Ok(services
+        .iter()
+        .filter(|(service_name, _)| {
+            name_patterns.is_empty()
+                || name_patterns
+                    .iter()
+                    .any(|pattern| matches_glob(pattern, service_name))
+        })
+        .filter(|(service_name, _)| {
+            !exclude_patterns
+                .iter()
+                .any(|pattern| matches_glob(pattern, service_name))
+        })
+        .filter(|(_, service_definition)| {
+            label_selectors
+                .iter()
+                .all(|selector| match selector.split_once('=') {
+                    None => service_definition.labels.contains_key(selector),
+                    Some((key, value)) => {
+                        service_definition
+                            .labels
+                            .get(key)
+                            .map(|label| label.as_str())
+                            == Some(value)
+                    }
+                })
+        })
+        .map(|(service_name, _)| service_name.clone())
+        .collect())
+}
+
+fn is_glob(pattern: &str) -> bool {
+    pattern.contains(['*', '?'])
+}
+
+// Supports "*" for any characters and "?" for a single character.
+fn matches_glob(pattern: &str, text: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let text = text.chars().collect::<Vec<_>>();
+    let (mut pattern_index, mut text_index) = (0, 0);
+    let mut backtrack = None;
+
+    while text_index < text.len() {
+        match pattern.get(pattern_index) {
+            Some('*') => {
+                backtrack = Some((pattern_index, text_index));
+                pattern_index += 1;
+            }
+            Some(&character) if character == '?' || character == text[text_index] => {
+                pattern_index += 1;
+                text_index += 1;
+            }
+            _ => match backtrack {
+                None => return false,
+                Some((star_index, star_text_index)) => {
+                    backtrack = Some((star_index, star_text_index + 1));
+                    pattern_index = star_index + 1;
+                    text_index = star_text_index + 1;
+                }
+            },
+        }
+    }
+
+    pattern[pattern_index..]
+        .iter()
+        .all(|&character| character == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case::test_case("api", "api", true; "literal")]
+    #[test_case::test_case("api", "apis", false; "literal prefix")]
+    #[test_case::test_case("api-*", "api-v1", true; "star suffix")]
+    #[test_case::test_case("api-*", "api-", true; "star empty")]
+    #[test_case::test_case("api-*", "web-v1", false; "star mismatch")]
+    #[test_case::test_case("*-worker", "mail-worker", true; "star prefix")]
+    #[test_case::test_case("a*b*c", "axxbyybc", true; "stars backtrack")]
+    #[test_case::test_case("a?c", "abc", true; "question mark")]
+    #[test_case::test_case("a?c", "ac", false; "question mark needs character")]
+    fn matches_glob_handles(pattern: &str, text: &str, expected: bool) {
+        assert_eq!(matches_glob(pattern, text), expected)
+    }
+
+    #[test_case::test_case(&[], &[], &[], &["api-v1", "api-v2", "web", "worker"]; "all")]
+    #[test_case::test_case(&["api-*"], &[], &[], &["api-v1", "api-v2"]; "glob")]
+    #[test_case::test_case(&["api-*", "web"], &[], &[], &["api-v1", "api-v2", "web"]; "globs")]
+    #[test_case::test_case(&[], &["api-*"], &[], &["web", "worker"]; "exclude")]
+    #[test_case::test_case(&[], &[], &["tier=frontend"], &["api-v2", "web"]; "label value")]
+    #[test_case::test_case(&[], &[], &["tier"], &["api-v2", "web", "worker"]; "label key")]
+    #[test_case::test_case(
+        &["api-*"], &["*-v1"], &["tier=frontend"], &["api-v2"]; "combined"
+    )]
+    #[test_case::test_case(&[], &[], &["tier=database"], &[]; "no match")]
+    fn select_handles(
+        name_patterns: &[&str],
+        exclude_patterns: &[&str],
+        label_selectors: &[&str],
+        expected: &[&str],
+    ) {
+        let services = serde_json::from_value(serde_json::json!({
+            "api-v1": {},
+            "api-v2": {"labels": {"tier": "frontend"}},
+            "web": {"labels": {"tier": "frontend"}},
+            "worker": {"labels": {"tier": "backend"}},
+        }))
+        .unwrap();
+
+        assert_eq!(
+            select(
+                &services,
+                &strings::<Vec<_>>(name_patterns),
+                &strings::<Vec<_>>(exclude_patterns),
+                &strings::<Vec<_>>(label_selectors),
+            )
+            .unwrap(),
+            strings(expected),
+        )
+    }
+
+    #[test]
+    fn select_rejects_unknown_literal_name() {
+        let services = serde_json::from_value(serde_json::json!({"api-v1": {}})).unwrap();
+
+        assert!(select(&services, &strings::<Vec<_>>(&["api-*", "web"]), &[], &[]).is_err())
+    }
+
+    fn strings<T: FromIterator<String>>(values: &[&str]) -> T {
+        values.iter().map(|value| (*value).into()).collect()
+    }
+}
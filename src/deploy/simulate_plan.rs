@@ -0,0 +1,262 @@
+use super::get_actual_state;
+use super::get_desired_state;
+use super::model;
+use super::plan_changes;
+use anyhow::Context;
+use std::collections;
+
+// Plans changes from snapshots instead of a container engine, which allows
+// trying out rollouts and keeping incidents as regression fixtures. The actual
+// containers are the output of `docker inspect`, and the desired state is the
+// output of `docker compose config --format json`, hashed natively. Services
+// of inactive profiles are left alone like by `docker compose up`.
+pub fn go(
+    In {
+        actual_containers,
+        compose_config,
+        force_recreate,
+        profiles,
+        remove_orphans,
+        strict_start_first,
+    }: In,
+) -> anyhow::Result<Vec<model::ServiceContainerChange>> {
+    let mut compose_config =
+        serde_json::from_str(compose_config).context("Unable to parse desired state")?;
+    let inactive_service_names = remove_inactive_services(&mut compose_config, profiles);
+    let desired_state = get_desired_state::read(compose_config, strict_start_first)
+        .context("Unable to read desired state")?;
+    let (actual_containers, orphan_containers) =
+        get_actual_state::read(actual_containers, &desired_state.project_name)
+            .context("Unable to read actual containers")?
+            .into_iter()
+            .filter(|container| !inactive_service_names.contains(&container.service_name))
+            .partition::<model::ActualContainers, _>(|container| {
+                desired_state.services.contains_key(&container.service_name)
+            });
+
+    let mut changes = plan_changes::go(&actual_containers, &desired_state.services, force_recreate);
+    if remove_orphans {
+        changes.extend(plan_changes::remove_orphans(&orphan_containers));
+    }
+    Ok(changes)
+}
+
+pub struct In<'a> {
+    pub actual_containers: &'a str,
+    pub compose_config: &'a str,
+    pub force_recreate: bool,
+    pub profiles: &'a [String],
+    pub remove_orphans: bool,
+    pub strict_start_first: bool,
+}
+
+// A service is inactive if it has profiles but none of them is enabled, with
+// "*" enabling all.
+fn remove_inactive_services(
+    compose_config: &mut serde_json::Value,
+    profiles: &[String],
+) -> collections::BTreeSet<String> {
+    let Some(services) = compose_config
+        .get_mut("services")
+        .and_then(|services| services.as_object_mut())
+    else {
+        return Default::default();
+    };
+    let is_enabled = |profile: &serde_json::Value| {
+        profiles
+            .iter()
+            .any(|enabled| enabled == "*" || profile.as_str() == Some(enabled))
+    };
+
+    let inactive_service_names = services
+        .iter()
+        .filter(|(_, service_definition)| {
+            service_definition
+                .get("profiles")
+                .and_then(|profiles| profiles.as_array())
+                .is_some_and(|profiles| !profiles.is_empty() && !profiles.iter().any(is_enabled))
+        })
+        .map(|(service_name, _)| service_name.clone())
+        .collect::<collections::BTreeSet<_>>();
+    services.retain(|service_name, _| !inactive_service_names.contains(service_name));
+    inactive_service_names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COMPOSE_CONFIG: &str = r#"{
+        "name": "my-project",
+        "services": {
+            "api": {
+                "deploy": {"replicas": 2, "update_config": {"order": "start-first"}},
+                "image": "api:2"
+            }
+        }
+    }"#;
+
+    #[test]
+    fn plans_rollout_of_changed_service() {
+        assert_eq!(
+            simulate(&actual_containers("old"), false),
+            [
+                add(),
+                model::ServiceContainerChange::Remove {
+                    container_id: "1".into(),
+                    service_config_hash: "old".into(),
+                    service_name: "api".into(),
+                },
+                add(),
+            ]
+        )
+    }
+
+    #[test]
+    fn keeps_unchanged_containers() {
+        assert_eq!(
+            simulate(&actual_containers(&get_api_hash()), false),
+            [
+                model::ServiceContainerChange::Keep {
+                    container_id: "1".into(),
+                    service_config_hash: get_api_hash(),
+                    service_name: "api".into(),
+                },
+                add(),
+            ]
+        )
+    }
+
+    #[test]
+    fn removes_orphans_if_asked() {
+        assert_eq!(
+            simulate(&actual_containers(&get_api_hash()), true).last(),
+            Some(&model::ServiceContainerChange::Remove {
+                container_id: "4".into(),
+                service_config_hash: get_api_hash(),
+                service_name: "worker".into(),
+            })
+        )
+    }
+
+    #[test]
+    fn rejects_container_without_label() {
+        let actual_containers = serde_json::json!([{
+            "Config": {
+                "Labels": {
+                    "com.docker.compose.oneoff": "False",
+                    "com.docker.compose.project": "my-project",
+                    "com.docker.compose.service": "api",
+                },
+            },
+            "Id": "1",
+            "Image": "sha256:0",
+        }])
+        .to_string();
+
+        let error = go(In {
+            actual_containers: &actual_containers,
+            compose_config: COMPOSE_CONFIG,
+            force_recreate: false,
+            profiles: &[],
+            remove_orphans: false,
+            strict_start_first: false,
+        })
+        .unwrap_err();
+
+        assert!(
+            format!("{error:#}").contains(r#"has no label "com.docker.compose.config-hash""#),
+            "{error:#}"
+        );
+    }
+
+    #[test_case::test_case(&[], false; "no profiles")]
+    #[test_case::test_case(&["debug"], true; "enabled profile")]
+    #[test_case::test_case(&["*"], true; "all profiles")]
+    fn leaves_services_of_inactive_profiles_alone(profiles: &[&str], is_active: bool) {
+        let profiles = profiles
+            .iter()
+            .map(|profile| profile.to_string())
+            .collect::<Vec<_>>();
+        let compose_config = serde_json::json!({
+            "name": "my-project",
+            "services": {
+                "api": {"image": "api:2"},
+                "worker": {"image": "worker:2", "profiles": ["debug"]},
+            },
+        })
+        .to_string();
+
+        let changes = go(In {
+            actual_containers: &actual_containers("old"),
+            compose_config: &compose_config,
+            force_recreate: false,
+            profiles: &profiles,
+            remove_orphans: true,
+            strict_start_first: false,
+        })
+        .unwrap();
+
+        assert_eq!(
+            changes.iter().any(|change| matches!(
+                change,
+                model::ServiceContainerChange::Remove { container_id, .. } if container_id == "4"
+            )),
+            is_active
+        )
+    }
+
+    fn actual_containers(api_hash: &str) -> String {
+        let container = |id: &str, project: &str, service: &str, one_off: &str| {
+            serde_json::json!({
+                "Config": {
+                    "Labels": {
+                        "com.docker.compose.config-hash": api_hash,
+                        "com.docker.compose.oneoff": one_off,
+                        "com.docker.compose.project": project,
+                        "com.docker.compose.service": service,
+                    },
+                },
+                "Id": id,
+                "Image": "sha256:0",
+            })
+        };
+        serde_json::json!([
+            container("1", "my-project", "api", "False"),
+            container("2", "my-project", "api", "True"),
+            container("3", "other-project", "api", "False"),
+            container("4", "my-project", "worker", "False"),
+        ])
+        .to_string()
+    }
+
+    fn simulate(
+        actual_containers: &str,
+        remove_orphans: bool,
+    ) -> Vec<model::ServiceContainerChange> {
+        go(In {
+            actual_containers,
+            compose_config: COMPOSE_CONFIG,
+            force_recreate: false,
+            profiles: &[],
+            remove_orphans,
+            strict_start_first: false,
+        })
+        .unwrap()
+    }
+
+    fn add() -> model::ServiceContainerChange {
+        model::ServiceContainerChange::Add {
+            service_config_hash: get_api_hash(),
+            service_name: "api".into(),
+        }
+    }
+
+    fn get_api_hash() -> String {
+        get_desired_state::read(serde_json::from_str(COMPOSE_CONFIG).unwrap(), false)
+            .unwrap()
+            .services["api"]
+            .service_config_hash
+            .clone()
+    }
+}
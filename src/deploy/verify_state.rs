@@ -0,0 +1,69 @@
+use super::model;
+use super::plan_changes;
+
+pub fn go(
+    actual_containers: &model::ActualContainers,
+    desired_services: &model::DesiredServices,
+    orphan_containers: &model::ActualContainers,
+) -> anyhow::Result<()> {
+    let outstanding_changes = plan_changes::go(actual_containers, desired_services, false)
+        .into_iter()
+        .chain(plan_changes::remove_orphans(orphan_containers))
+        .filter(|change| !matches!(change, model::ServiceContainerChange::Keep { .. }))
+        .collect::<Vec<_>>();
+
+    if outstanding_changes.is_empty() {
+        Ok(())
+    } else {
+        Err(anyhow::anyhow!(
+            "Actual state differs from desired state after deployment, \
+            outstanding changes: {outstanding_changes:?}"
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case::test_case(&[], 0, false, true; "no containers wanted")]
+    #[test_case::test_case(&["a"], 1, false, true; "all up to date")]
+    #[test_case::test_case(&["b"], 1, false, false; "outdated container")]
+    #[test_case::test_case(&["a"], 2, false, false; "missing container")]
+    #[test_case::test_case(&["a", "a"], 1, false, false; "extra container")]
+    #[test_case::test_case(&["a"], 1, true, false; "orphan container")]
+    fn handles(actual_hashes: &[&str], replica_count: u16, has_orphan: bool, expected: bool) {
+        let actual_containers = actual_hashes
+            .iter()
+            .enumerate()
+            .map(|(index, hash)| model::ActualContainer {
+                container_id: index.to_string(),
+                image_id: "".into(),
+                service_config_hash: (*hash).into(),
+                service_name: "x".into(),
+            })
+            .collect();
+        let desired_services = [(
+            "x".into(),
+            model::DesiredServiceDefinition {
+                replica_count,
+                ..model::DesiredServiceDefinition::new("a")
+            },
+        )]
+        .into();
+        let orphan_containers = has_orphan
+            .then(|| model::ActualContainer {
+                container_id: "orphan".into(),
+                image_id: "".into(),
+                service_config_hash: "a".into(),
+                service_name: "y".into(),
+            })
+            .into_iter()
+            .collect();
+
+        assert_eq!(
+            go(&actual_containers, &desired_services, &orphan_containers).is_ok(),
+            expected
+        )
+    }
+}
@@ -0,0 +1,153 @@
+use super::backend;
+use super::Forward;
+use super::Host;
+use crate::log;
+use anyhow::Context;
+use std::collections;
+use std::fs;
+use std::io::Write;
+use std::os::unix::fs::PermissionsExt;
+use std::path;
+
+// Lists the host addresses of the containers of the service, one per line, for
+// `wheelsticks forward`. The file is replaced atomically so that the forwarder
+// switches backends at once.
+pub fn go(
+    In {
+        backend,
+        excluded_container_id,
+        forward:
+            Forward {
+                backends_file,
+                container_port,
+                service_name,
+            },
+    }: In,
+) -> anyhow::Result<()> {
+    let service_names = collections::BTreeSet::from([service_name.clone()]);
+    let addresses = backend
+        .list_containers(&service_names)?
+        .into_iter()
+        .filter(|container| Some(container.container_id.as_str()) != excluded_container_id)
+        .map(|container| backend.get_published_address(&container.container_id, *container_port))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    log::debug!("Forwarding to {addresses:?} for service {service_name:?}.");
+    let folder = match backends_file.parent() {
+        Some(folder) if folder != path::Path::new("") => folder,
+        _ => path::Path::new("."),
+    };
+    let mut file = tempfile::NamedTempFile::new_in(folder)?;
+    for address in addresses {
+        writeln!(file, "{address}")?;
+    }
+    // The forwarder may run as another user.
+    file.as_file()
+        .set_permissions(fs::Permissions::from_mode(0o644))?;
+    file.persist(backends_file)
+        .with_context(|| format!("Unable to write backends file {backends_file:?}"))?;
+    Ok(())
+}
+
+// The file is written on this machine but lists addresses published on the host
+// of the container engine, so both must be the same. Thus, there can only be a
+// single host, with a local container engine.
+pub fn check_hosts(hosts: &[Host]) -> anyhow::Result<()> {
+    let [Host { docker_cli, .. }] = hosts else {
+        anyhow::bail!(
+            "Forwarding supports only a single host, but got {}; \
+            run `wheelsticks deploy` on each host instead",
+            hosts.len()
+        );
+    };
+    let endpoint = docker_cli
+        .endpoint()
+        .context("Unable to get container engine endpoint")?;
+    if !["unix://", "npipe://"]
+        .iter()
+        .any(|scheme| endpoint.starts_with(scheme))
+    {
+        anyhow::bail!(
+            "Forwarding needs a local container engine, but got {endpoint:?}; \
+            run `wheelsticks deploy` on that host instead"
+        );
+    }
+    Ok(())
+}
+
+pub struct In<'a> {
+    pub backend: &'a dyn backend::Backend,
+    pub excluded_container_id: Option<&'a str>,
+    pub forward: &'a Forward,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::fake_backend;
+    use super::super::model;
+    use super::*;
+    use crate::docker;
+
+    #[test_case::test_case(None, "x0:80\nx1:80\n"; "all")]
+    #[test_case::test_case(Some("x0"), "x1:80\n"; "excluded")]
+    fn handles(excluded_container_id: Option<&str>, expected: &str) -> anyhow::Result<()> {
+        let folder = tempfile::tempdir()?;
+        let forward = Forward {
+            backends_file: folder.path().join("backends"),
+            container_port: 80,
+            service_name: "x".into(),
+        };
+        let actual_containers = ["x0", "x1"]
+            .into_iter()
+            .map(|container_id| model::ActualContainer {
+                container_id: container_id.into(),
+                image_id: "".into(),
+                service_config_hash: "a".into(),
+                service_name: "x".into(),
+            })
+            .collect();
+        let backend = fake_backend::FakeBackend::new(&actual_containers, &Default::default());
+
+        go(In {
+            backend: &backend,
+            excluded_container_id,
+            forward: &forward,
+        })?;
+
+        assert_eq!(fs::read_to_string(&forward.backends_file)?, expected);
+        let mode = fs::metadata(&forward.backends_file)?.permissions().mode();
+        assert_eq!(mode & 0o777, 0o644);
+        Ok(())
+    }
+
+    #[test_case::test_case(&["unix:///var/run/docker.sock"], true; "local")]
+    #[test_case::test_case(&["ssh://deploy@example.com"], false; "remote")]
+    #[test_case::test_case(&["tcp://10.0.0.2:2376"], false; "remote over TCP")]
+    #[test_case::test_case(&["unix:///a.sock", "unix:///b.sock"], false; "multiple")]
+    fn check_hosts_handles(endpoints: &[&str], expected: bool) {
+        let hosts = endpoints
+            .iter()
+            .map(|endpoint| Host {
+                context: None,
+                docker_cli: docker::Cli::new(
+                    "docker",
+                    docker::Arguments {
+                        config: None,
+                        context: None,
+                        debug: false,
+                        host: Some(endpoint),
+                        log_level: None,
+                        tls: false,
+                        tlscacert: None,
+                        tlscert: None,
+                        tlskey: None,
+                        tlsverify: false,
+                    },
+                ),
+                projects: vec![],
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(check_hosts(&hosts).is_ok(), expected)
+    }
+}
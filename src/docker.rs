@@ -1,3 +1,5 @@
+use super::command;
+use std::env;
 use std::process;
 
 pub struct Cli<'a> {
@@ -81,4 +83,25 @@ impl<'a> Cli<'a> {
     pub fn command_default_daemon(&self) -> process::Command {
         self.base(true)
     }
+
+    // Precedence as in Docker CLI: `--host`, `--context`, `DOCKER_HOST`, then
+    // current context.
+    pub fn endpoint(&self) -> anyhow::Result<String> {
+        match (self.arguments.host, self.arguments.context) {
+            (Some(host), _) => Ok(host.into()),
+            (None, None) if env::var_os("DOCKER_HOST").is_some() => Ok(env::var("DOCKER_HOST")?),
+            _ => {
+                let endpoint = command::stdout_utf8(
+                    self.command().args([
+                        "context",
+                        "inspect",
+                        "--format",
+                        "{{.Endpoints.docker.Host}}",
+                    ]),
+                    command::Stderr::Inherit,
+                )?;
+                Ok(endpoint.trim().into())
+            }
+        }
+    }
 }
@@ -6,6 +6,13 @@ pub struct Cli<'a> {
     docker_cli: docker::Cli<'a>,
 }
 
+// A port of a service as in `docker compose config --format json`.
+#[derive(serde::Deserialize)]
+pub struct Port {
+    pub host_ip: Option<String>,
+    pub published: Option<String>,
+}
+
 pub struct Arguments<'a> {
     pub ansi: Option<&'a str>,
     pub compatibility: bool,
@@ -30,6 +37,16 @@ impl<'a> Cli<'a> {
     }
 
     pub fn command(&self) -> process::Command {
+        self.command_with_profiles(self.arguments.profile)
+    }
+
+    // Like `command` but with all profiles enabled, for example to tell
+    // services of inactive profiles apart from removed ones.
+    pub fn command_with_all_profiles(&self) -> process::Command {
+        self.command_with_profiles(&["*".into()])
+    }
+
+    fn command_with_profiles(&self, profile: &[String]) -> process::Command {
         let mut command = self.docker_cli.command();
 
         let Arguments {
@@ -38,7 +55,7 @@ impl<'a> Cli<'a> {
             env_file,
             file,
             parallel,
-            profile,
+            profile: _,
             progress,
             project_directory,
             project_name,
@@ -80,3 +97,39 @@ impl<'a> Cli<'a> {
         command
     }
 }
+
+impl Port {
+    // A fixed host port allows only one container to publish it. A range of
+    // published ports leaves the choice of a free port to the container engine.
+    pub fn get_fixed_host_port(&self) -> Option<String> {
+        let published = self.published.as_deref()?;
+        (!published.is_empty() && !published.contains('-')).then(|| match self.host_ip.as_deref() {
+            None | Some("") => published.into(),
+            Some(host_ip) => format!("{host_ip}:{published}"),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case::test_case(None, Some("8080"), Some("8080"); "published")]
+    #[test_case::test_case(Some(""), Some("8080"), Some("8080"); "empty host IP")]
+    #[test_case::test_case(Some("127.0.0.1"), Some("8080"), Some("127.0.0.1:8080"); "host IP")]
+    #[test_case::test_case(None, Some("9000-9010"), None; "range")]
+    #[test_case::test_case(None, Some(""), None; "empty")]
+    #[test_case::test_case(None, None, None; "unpublished")]
+    fn get_fixed_host_port_handles(
+        host_ip: Option<&str>,
+        published: Option<&str>,
+        expected: Option<&str>,
+    ) {
+        let port = Port {
+            host_ip: host_ip.map(|host_ip| host_ip.into()),
+            published: published.map(|published| published.into()),
+        };
+
+        assert_eq!(port.get_fixed_host_port().as_deref(), expected)
+    }
+}
@@ -0,0 +1,345 @@
+use super::http;
+use super::log;
+use anyhow::Context;
+use serde::de;
+use std::collections;
+use std::os::unix::net;
+use std::path;
+use std::process;
+use std::thread;
+use std::time;
+
+// Limits waiting on an unresponsive daemon, on top of any time a request is
+// expected to take, as with `http::connect`.
+const TIMEOUT: time::Duration = time::Duration::from_secs(30);
+
+// Applies to containers created without a stop timeout.
+const DEFAULT_STOP_TIMEOUT: i64 = 10;
+
+pub struct Client {
+    socket: path::PathBuf,
+    timeout: time::Duration,
+    _ssh_forwarding: Option<SshForwarding>,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct ContainerSummary {
+    pub id: String,
+    #[serde(rename = "ImageID")]
+    pub image_id: String,
+    pub labels: collections::BTreeMap<String, String>,
+}
+
+struct SshForwarding {
+    ssh: process::Child,
+    _folder: tempfile::TempDir,
+}
+
+impl Drop for SshForwarding {
+    fn drop(&mut self) {
+        let _ = self.ssh.kill();
+        let _ = self.ssh.wait();
+    }
+}
+
+impl Client {
+    pub fn connect(endpoint: &str) -> anyhow::Result<Self> {
+        let client = match endpoint.split_once("://") {
+            Some(("unix", socket)) => Self {
+                socket: socket.into(),
+                timeout: TIMEOUT,
+                _ssh_forwarding: None,
+            },
+            Some(("ssh", _)) => forward_over_ssh(endpoint)?,
+            _ => anyhow::bail!(
+                "Unsupported endpoint {endpoint:?}, expected \"unix://…\" or \"ssh://…\""
+            ),
+        };
+
+        let response = client.send("GET", "/_ping")?;
+        if response.is_success() {
+            Ok(client)
+        } else {
+            Err(error_from(response)).with_context(|| format!("Unable to ping {endpoint:?}"))
+        }
+    }
+
+    pub fn inspect_container<T: de::DeserializeOwned>(
+        &self,
+        container_id: &str,
+    ) -> anyhow::Result<Option<T>> {
+        let response = self.send("GET", &format!("/containers/{container_id}/json"))?;
+        match response.status {
+            404 => Ok(None),
+            _ => json_from(response).map(Some),
+        }
+    }
+
+    pub fn list_containers(&self, labels: &[String]) -> anyhow::Result<Vec<ContainerSummary>> {
+        let filters = serde_json::json!({ "label": labels }).to_string();
+        let filters = percent_encode(&filters);
+        json_from(self.send(
+            "GET",
+            &format!("/containers/json?all=true&filters={filters}"),
+        )?)
+    }
+
+    pub fn remove_container(&self, container_id: &str) -> anyhow::Result<()> {
+        empty_from(self.send("DELETE", &format!("/containers/{container_id}"))?)
+    }
+
+    // Without a timeout in seconds, the one the container was created with
+    // applies, where a negative one waits for the container to exit.
+    pub fn stop_container(&self, container_id: &str, timeout: Option<&str>) -> anyhow::Result<()> {
+        #[derive(serde::Deserialize)]
+        #[serde(rename_all = "PascalCase")]
+        struct Container {
+            config: Config,
+        }
+
+        #[derive(serde::Deserialize)]
+        #[serde(rename_all = "PascalCase")]
+        struct Config {
+            stop_timeout: Option<i64>,
+        }
+
+        let stop_timeout = match timeout {
+            None => self
+                .inspect_container::<Container>(container_id)?
+                .and_then(|container| container.config.stop_timeout)
+                .unwrap_or(DEFAULT_STOP_TIMEOUT),
+            Some(timeout) => timeout
+                .parse()
+                .with_context(|| format!("Invalid stop timeout {timeout:?}"))?,
+        };
+        let read_timeout = u64::try_from(stop_timeout)
+            .ok()
+            .map(|stop_timeout| self.timeout + time::Duration::from_secs(stop_timeout));
+
+        let query = timeout.map_or("".into(), |timeout| format!("?t={timeout}"));
+        let response = self.send_within(
+            "POST",
+            &format!("/containers/{container_id}/stop{query}"),
+            read_timeout,
+        )?;
+        match response.status {
+            // Container already stopped.
+            304 => Ok(()),
+            _ => empty_from(response),
+        }
+    }
+
+    fn send(&self, method: &str, target: &str) -> anyhow::Result<http::Response> {
+        self.send_within(method, target, Some(self.timeout))
+    }
+
+    // Without a read timeout, waits as long as the daemon takes to respond.
+    fn send_within(
+        &self,
+        method: &str,
+        target: &str,
+        read_timeout: Option<time::Duration>,
+    ) -> anyhow::Result<http::Response> {
+        let socket = &self.socket;
+        log::debug!("Requesting {method} {target} from Engine API at {socket:?}.");
+
+        let stream = net::UnixStream::connect(socket)
+            .map_err(http::TransportError)
+            .with_context(|| format!("Unable to connect to {socket:?}"))?;
+        stream.set_read_timeout(read_timeout)?;
+        stream.set_write_timeout(Some(self.timeout))?;
+        http::send(
+            stream,
+            http::Request {
+                body: None,
+                host: "docker",
+                method,
+                target,
+            },
+        )
+        .with_context(|| format!("Unable to request {method} {target} from {socket:?}"))
+    }
+}
+
+fn forward_over_ssh(endpoint: &str) -> anyhow::Result<Client> {
+    let folder = tempfile::tempdir()?;
+    let socket = folder.path().join("docker.sock");
+
+    log::debug!("Forwarding Engine API of {endpoint:?} to {socket:?}.");
+    let mut ssh = process::Command::new("ssh")
+        .args(["-nNT", "-o", "ExitOnForwardFailure=yes", "-L"])
+        .arg(format!("{}:/var/run/docker.sock", socket.display()))
+        .arg(endpoint)
+        .spawn()
+        .context("Unable to run SSH")?;
+
+    let deadline = time::Instant::now() + time::Duration::from_secs(10);
+    while !socket.exists() {
+        if let Some(status) = ssh.try_wait()? {
+            anyhow::bail!("SSH forwarding to {endpoint:?} ended early: {status}");
+        }
+        if time::Instant::now() > deadline {
+            let _ = ssh.kill();
+            anyhow::bail!("SSH forwarding to {endpoint:?} timed out");
+        }
+        thread::sleep(time::Duration::from_millis(50));
+    }
+
+    Ok(Client {
+        socket,
+        timeout: TIMEOUT,
+        _ssh_forwarding: Some(SshForwarding {
+            ssh,
+            _folder: folder,
+        }),
+    })
+}
+
+fn json_from<T: de::DeserializeOwned>(response: http::Response) -> anyhow::Result<T> {
+    if response.is_success() {
+        serde_json::from_slice(&response.body).context("Unable to deserialize JSON response")
+    } else {
+        Err(error_from(response))
+    }
+}
+
+fn empty_from(response: http::Response) -> anyhow::Result<()> {
+    if response.is_success() {
+        Ok(())
+    } else {
+        Err(error_from(response))
+    }
+}
+
+fn error_from(http::Response { body, status }: http::Response) -> anyhow::Error {
+    #[derive(serde::Deserialize)]
+    struct Error {
+        message: String,
+    }
+
+    let message = match serde_json::from_slice::<Error>(&body) {
+        Ok(Error { message }) => message,
+        Err(_) => String::from_utf8_lossy(&body).into_owned(),
+    };
+    http::StatusError { message, status }.into()
+}
+
+fn percent_encode(text: &str) -> String {
+    text.bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                char::from(byte).to_string()
+            }
+            _ => format!("%{byte:02X}"),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::BufRead;
+    use std::io::Write;
+    use std::sync;
+
+    #[test]
+    fn handles_stub_server() -> anyhow::Result<()> {
+        let (endpoint, requests, _folder) = serve_stub(vec![
+            "HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nOK",
+            "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n\
+            2d\r\n[{\"Id\":\"a\",\"ImageID\":\"b\",\"Labels\":{\"x\":\"y\"}}]\r\n0\r\n\r\n",
+            "HTTP/1.1 304 Not Modified\r\n\r\n",
+            "HTTP/1.1 204 No Content\r\n\r\n",
+            "HTTP/1.1 404 Not Found\r\nContent-Length: 27\r\n\r\n{\"message\":\"No such thing\"}",
+            "HTTP/1.1 409 Conflict\r\nContent-Length: 20\r\n\r\n{\"message\":\"In use\"}",
+        ])?;
+
+        let client = Client::connect(&endpoint)?;
+        let containers = client.list_containers(&["x=y".into()])?;
+        client.stop_container("a", Some("30"))?;
+        client.remove_container("a")?;
+        let container = client.inspect_container::<de::IgnoredAny>("a")?;
+        let error = client.remove_container("a").unwrap_err();
+
+        assert_eq!(containers.len(), 1);
+        assert_eq!(containers[0].id, "a");
+        assert_eq!(containers[0].labels["x"], "y");
+        assert!(container.is_none());
+        assert_eq!(error.to_string(), "HTTP status 409: In use");
+        assert_eq!(
+            *requests.lock().unwrap(),
+            [
+                "GET /_ping HTTP/1.1",
+                "GET /containers/json?all=true&filters=%7B%22label%22%3A%5B%22x%3Dy%22%5D%7D \
+                HTTP/1.1",
+                "POST /containers/a/stop?t=30 HTTP/1.1",
+                "DELETE /containers/a HTTP/1.1",
+                "GET /containers/a/json HTTP/1.1",
+                "DELETE /containers/a HTTP/1.1",
+            ],
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn times_out_on_unresponsive_daemon() -> anyhow::Result<()> {
+        let folder = tempfile::tempdir()?;
+        let socket = folder.path().join("docker.sock");
+        let _listener = net::UnixListener::bind(&socket)?;
+        let client = Client {
+            socket,
+            timeout: time::Duration::from_millis(100),
+            _ssh_forwarding: None,
+        };
+
+        let start = time::Instant::now();
+        let error = client.remove_container("a").unwrap_err();
+
+        assert!(start.elapsed() < 10 * client.timeout);
+        assert!(error
+            .chain()
+            .any(|cause| cause.is::<http::TransportError>()));
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_unsupported_endpoint() {
+        assert!(Client::connect("tcp://localhost:2375").is_err())
+    }
+
+    type Requests = sync::Arc<sync::Mutex<Vec<String>>>;
+
+    fn serve_stub(
+        responses: Vec<&'static str>,
+    ) -> anyhow::Result<(String, Requests, tempfile::TempDir)> {
+        let folder = tempfile::tempdir()?;
+        let socket = folder.path().join("docker.sock");
+        let listener = net::UnixListener::bind(&socket)?;
+        let requests = Requests::default();
+        let requests_ = requests.clone();
+
+        thread::spawn(move || {
+            for response in responses {
+                let (stream, _) = listener.accept().unwrap();
+                let mut stream = std::io::BufReader::new(stream);
+                let mut request_line = String::new();
+                stream.read_line(&mut request_line).unwrap();
+                requests_
+                    .lock()
+                    .unwrap()
+                    .push(request_line.trim_end().into());
+                loop {
+                    let mut header = String::new();
+                    stream.read_line(&mut header).unwrap();
+                    if header.trim_end().is_empty() {
+                        break;
+                    }
+                }
+                stream.get_mut().write_all(response.as_bytes()).unwrap();
+            }
+        });
+
+        Ok((format!("unix://{}", socket.display()), requests, folder))
+    }
+}
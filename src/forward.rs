@@ -0,0 +1,147 @@
+use super::log;
+use anyhow::Context;
+use std::fs;
+use std::io;
+use std::net;
+use std::path;
+use std::sync;
+use std::sync::atomic;
+use std::thread;
+
+pub fn go(
+    In {
+        backends_file,
+        listen,
+    }: In,
+) -> anyhow::Result<()> {
+    let listener =
+        net::TcpListener::bind(&listen).with_context(|| format!("Unable to listen on {listen}"))?;
+    log::info!("Forwarding {listen} to backends in {backends_file:?}.");
+
+    let backends_file = sync::Arc::new(backends_file);
+    let next_backend_index = sync::Arc::new(atomic::AtomicUsize::new(0));
+
+    for client in listener.incoming() {
+        let client = match client {
+            Ok(client) => client,
+            Err(error) => {
+                log::warn!("Unable to accept connection: {error}");
+                continue;
+            }
+        };
+        let backends_file = backends_file.clone();
+        let next_backend_index = next_backend_index.clone();
+
+        thread::spawn(move || {
+            if let Err(error) = forward(client, &backends_file, &next_backend_index) {
+                log::warn!("Unable to forward connection: {error:#}");
+            }
+        });
+    }
+
+    Ok(())
+}
+
+pub struct In {
+    pub backends_file: path::PathBuf,
+    pub listen: String,
+}
+
+// The backends are read for each connection, so replacing the file switches
+// new connections over at once, whereas open connections are left alone.
+fn forward(
+    client: net::TcpStream,
+    backends_file: &path::Path,
+    next_backend_index: &atomic::AtomicUsize,
+) -> anyhow::Result<()> {
+    let backends = read_backends(backends_file)?;
+    let start_index = next_backend_index.fetch_add(1, atomic::Ordering::Relaxed);
+
+    // Round robin, trying the next backend if one is unavailable.
+    let backend = (0..backends.len())
+        .map(|offset| &backends[(start_index + offset) % backends.len()])
+        .find_map(|backend| match net::TcpStream::connect(backend) {
+            Ok(stream) => Some(stream),
+            Err(error) => {
+                log::debug!("Backend {backend} is unavailable: {error}");
+                None
+            }
+        })
+        .context("No backend is available")?;
+
+    let upload = copy_then_shutdown(client.try_clone()?, backend.try_clone()?);
+    let download = copy_then_shutdown(backend, client);
+    upload
+        .join()
+        .map_err(|_| anyhow::anyhow!("Upload panicked"))?;
+    download
+        .join()
+        .map_err(|_| anyhow::anyhow!("Download panicked"))?;
+    Ok(())
+}
+
+fn read_backends(backends_file: &path::Path) -> anyhow::Result<Vec<String>> {
+    let backends = fs::read_to_string(backends_file)
+        .with_context(|| format!("Unable to read backends file {backends_file:?}"))?;
+    Ok(backends
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(|line| line.into())
+        .collect())
+}
+
+fn copy_then_shutdown(
+    mut reader: net::TcpStream,
+    mut writer: net::TcpStream,
+) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        let _ = io::copy(&mut reader, &mut writer);
+        let _ = writer.shutdown(net::Shutdown::Write);
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use std::io::Write;
+
+    #[test]
+    fn handles() -> anyhow::Result<()> {
+        let folder = tempfile::tempdir()?;
+        let backends_file = folder.path().join("backends");
+        let unavailable = net::TcpListener::bind("127.0.0.1:0")?.local_addr()?;
+        let echo_server = net::TcpListener::bind("127.0.0.1:0")?;
+        let echo_address = echo_server.local_addr()?;
+        fs::write(&backends_file, format!("{unavailable}\n{echo_address}\n"))?;
+        thread::spawn(move || -> anyhow::Result<()> {
+            for connection in echo_server.incoming() {
+                let mut connection = connection?;
+                let mut request = String::new();
+                connection.read_to_string(&mut request)?;
+                connection.write_all(request.to_uppercase().as_bytes())?;
+            }
+            Ok(())
+        });
+        let listener = net::TcpListener::bind("127.0.0.1:0")?;
+        let address = listener.local_addr()?;
+        let next_backend_index = atomic::AtomicUsize::new(0);
+        let proxy = thread::spawn(move || -> anyhow::Result<()> {
+            let (client, _) = listener.accept()?;
+            forward(client, &backends_file, &next_backend_index)
+        });
+
+        let mut client = net::TcpStream::connect(address)?;
+        client.write_all(b"hi")?;
+        client.shutdown(net::Shutdown::Write)?;
+        let mut response = String::new();
+        client.read_to_string(&mut response)?;
+
+        assert_eq!(response, "HI");
+        proxy
+            .join()
+            .map_err(|_| anyhow::anyhow!("Proxy panicked"))??;
+        Ok(())
+    }
+}
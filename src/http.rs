@@ -0,0 +1,258 @@
+use anyhow::Context;
+use std::fmt;
+use std::io;
+use std::io::BufRead;
+use std::io::Read;
+use std::io::Write;
+use std::net;
+use std::net::ToSocketAddrs;
+use std::time;
+
+pub struct Request<'a> {
+    pub body: Option<Body<'a>>,
+    pub host: &'a str,
+    pub method: &'a str,
+    pub target: &'a str,
+}
+
+pub struct Body<'a> {
+    pub content: &'a [u8],
+    pub content_type: &'a str,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Response {
+    pub body: Vec<u8>,
+    pub status: u16,
+}
+
+impl Response {
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+}
+
+// Response with a status other than 2xx. Server errors may be transient.
+#[derive(Debug)]
+pub struct StatusError {
+    pub message: String,
+    pub status: u16,
+}
+
+impl StatusError {
+    pub fn is_server_error(&self) -> bool {
+        (500..600).contains(&self.status)
+    }
+}
+
+impl fmt::Display for StatusError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "HTTP status {}: {}", self.status, self.message)
+    }
+}
+
+impl std::error::Error for StatusError {}
+
+// Failure to exchange a request and response, like a reset connection, which
+// may be transient.
+#[derive(Debug)]
+pub struct TransportError(pub io::Error);
+
+impl fmt::Display for TransportError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "{}", self.0)
+    }
+}
+
+impl std::error::Error for TransportError {}
+
+// Connects with timeouts for connecting, reading, and writing, so that an
+// unresponsive server cannot hang the caller.
+pub fn connect(host: &str, timeout: time::Duration) -> anyhow::Result<net::TcpStream> {
+    let address = host
+        .to_socket_addrs()
+        .with_context(|| format!("Unable to resolve {host:?}"))?
+        .next()
+        .with_context(|| format!("No address for {host:?}"))?;
+    let stream = net::TcpStream::connect_timeout(&address, timeout)
+        .map_err(TransportError)
+        .with_context(|| format!("Unable to connect to {host:?}"))?;
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+    Ok(stream)
+}
+
+pub fn send<T: Read + Write>(
+    stream: T,
+    Request {
+        body,
+        host,
+        method,
+        target,
+    }: Request,
+) -> anyhow::Result<Response> {
+    let mut stream = io::BufReader::new(stream);
+
+    let (content_type, content) = body
+        .map(|body| (Some(body.content_type), body.content))
+        .unwrap_or_default();
+    let mut request = format!(
+        "{method} {target} HTTP/1.1\r\n\
+        Host: {host}\r\n\
+        Connection: close\r\n\
+        Content-Length: {}\r\n",
+        content.len(),
+    );
+    if let Some(content_type) = content_type {
+        request.push_str(&format!("Content-Type: {content_type}\r\n"));
+    }
+    request.push_str("\r\n");
+
+    let writer = stream.get_mut();
+    writer
+        .write_all(request.as_bytes())
+        .and_then(|()| writer.write_all(content))
+        .and_then(|()| writer.flush())
+        .map_err(TransportError)
+        .context("Unable to send HTTP request")?;
+
+    read_response(&mut stream).context("Unable to read HTTP response")
+}
+
+fn read_response(stream: &mut impl BufRead) -> anyhow::Result<Response> {
+    let status_line = read_line(stream)?;
+    let status = status_line
+        .split_whitespace()
+        .nth(1)
+        .and_then(|status| status.parse().ok())
+        .with_context(|| format!("Invalid status line: {status_line:?}"))?;
+
+    let mut content_length = None;
+    let mut is_chunked = false;
+
+    loop {
+        let header = read_line(stream)?;
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            let value = value.trim();
+            if name.eq_ignore_ascii_case("Content-Length") {
+                content_length = Some(value.parse().context("Invalid content length")?);
+            } else if name.eq_ignore_ascii_case("Transfer-Encoding") {
+                is_chunked = value.eq_ignore_ascii_case("chunked");
+            }
+        }
+    }
+
+    let mut body = vec![];
+    if is_chunked {
+        loop {
+            let size = read_line(stream)?;
+            let size = size.split(';').next().unwrap_or_default().trim();
+            let size = usize::from_str_radix(size, 16)
+                .with_context(|| format!("Invalid chunk size: {size:?}"))?;
+            if size == 0 {
+                break;
+            }
+            let start = body.len();
+            body.resize(start + size, 0);
+            stream
+                .read_exact(&mut body[start..])
+                .map_err(TransportError)?;
+            read_line(stream)?;
+        }
+    } else if let Some(content_length) = content_length {
+        body.resize(content_length, 0);
+        stream.read_exact(&mut body).map_err(TransportError)?;
+    } else if !(status == 204 || status == 304) {
+        stream.read_to_end(&mut body).map_err(TransportError)?;
+    }
+
+    Ok(Response { body, status })
+}
+
+fn read_line(stream: &mut impl BufRead) -> anyhow::Result<String> {
+    let mut line = String::new();
+    stream.read_line(&mut line).map_err(TransportError)?;
+    Ok(line.trim_end_matches(['\r', '\n']).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case::test_case(
+        "HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nHi",
+        Response { body: "Hi".into(), status: 200 };
+        "content length"
+    )]
+    #[test_case::test_case(
+        "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n2\r\nHi\r\n3;x=y\r\n yo\r\n0\r\n\r\n",
+        Response { body: "Hi yo".into(), status: 200 };
+        "chunked"
+    )]
+    #[test_case::test_case(
+        "HTTP/1.0 404 Not Found\r\n\r\nGone",
+        Response { body: "Gone".into(), status: 404 };
+        "until end"
+    )]
+    #[test_case::test_case(
+        "HTTP/1.1 204 No Content\r\n\r\n",
+        Response { body: vec![], status: 204 };
+        "no content"
+    )]
+    fn send_handles(response: &str, expected: Response) -> anyhow::Result<()> {
+        let mut stream = Stream {
+            input: io::Cursor::new(response.as_bytes().into()),
+            output: vec![],
+        };
+
+        let actual = send(
+            &mut stream,
+            Request {
+                body: Some(Body {
+                    content: b"{}",
+                    content_type: "application/json",
+                }),
+                host: "localhost",
+                method: "POST",
+                target: "/x?y=z",
+            },
+        )?;
+
+        assert_eq!(actual, expected);
+        assert_eq!(
+            String::from_utf8(stream.output)?,
+            "POST /x?y=z HTTP/1.1\r\n\
+            Host: localhost\r\n\
+            Connection: close\r\n\
+            Content-Length: 2\r\n\
+            Content-Type: application/json\r\n\
+            \r\n\
+            {}",
+        );
+        Ok(())
+    }
+
+    struct Stream {
+        input: io::Cursor<Vec<u8>>,
+        output: Vec<u8>,
+    }
+
+    impl Read for Stream {
+        fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+            self.input.read(buffer)
+        }
+    }
+
+    impl Write for Stream {
+        fn write(&mut self, buffer: &[u8]) -> io::Result<usize> {
+            self.output.write(buffer)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+}
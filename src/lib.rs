@@ -0,0 +1,13 @@
+//! Zero-downtime deployments for Docker Compose.
+//!
+//! The entry point is [`deploy::go`], which plans and applies changes like
+//! `wheelsticks deploy`. Use [`deploy::plan_changes::go`] to only plan changes
+//! for given actual and desired states.
+
+pub mod command;
+pub mod deploy;
+pub mod docker;
+pub mod docker_compose;
+mod engine_api;
+mod http;
+pub mod log;
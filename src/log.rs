@@ -1,6 +1,11 @@
+use std::fmt;
+use std::io;
+use std::io::Write;
 use std::sync;
+use std::time;
 
 static LEVEL: sync::OnceLock<Level> = sync::OnceLock::new();
+static FORMAT: sync::OnceLock<Format> = sync::OnceLock::new();
 
 #[derive(Clone, Copy, Eq, Ord, PartialEq, PartialOrd)]
 pub enum Level {
@@ -10,6 +15,35 @@ pub enum Level {
     Error,
 }
 
+#[derive(Clone, Copy, Eq, PartialEq)]
+pub enum Format {
+    Text,
+    Json,
+}
+
+// Optional context of an event. Text logs show only the message, whereas JSON
+// logs include the set fields for indexing.
+#[derive(Default, serde::Serialize)]
+pub struct Fields<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub change_kind: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub command: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub container_id: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub service: Option<&'a str>,
+}
+
+#[derive(serde::Serialize)]
+struct Event<'a> {
+    timestamp: String,
+    level: &'static str,
+    message: String,
+    #[serde(flatten)]
+    fields: &'a Fields<'a>,
+}
+
 pub fn level() -> Level {
     *LEVEL.get().unwrap_or(&Level::Debug)
 }
@@ -20,33 +54,157 @@ pub fn set_level(level: Level) -> anyhow::Result<()> {
         .map_err(|_| anyhow::anyhow!("Log level set twice"))
 }
 
+pub fn format() -> Format {
+    *FORMAT.get().unwrap_or(&Format::Text)
+}
+
+pub fn set_format(format: Format) -> anyhow::Result<()> {
+    FORMAT
+        .set(format)
+        .map_err(|_| anyhow::anyhow!("Log format set twice"))
+}
+
+pub fn write(level: Level, fields: &Fields, message: fmt::Arguments) {
+    if self::level() <= level {
+        let line = match format() {
+            Format::Text => message.to_string(),
+            Format::Json => serialize_event(time::SystemTime::now(), level, fields, message),
+        };
+        let _ = writeln!(io::stderr(), "{line}");
+    }
+}
+
+fn serialize_event(
+    time: time::SystemTime,
+    level: Level,
+    fields: &Fields,
+    message: fmt::Arguments,
+) -> String {
+    let event = Event {
+        timestamp: format_timestamp(time),
+        level: match level {
+            Level::Debug => "debug",
+            Level::Info => "info",
+            Level::Warn => "warn",
+            Level::Error => "error",
+        },
+        message: message.to_string(),
+        fields,
+    };
+    serde_json::to_string(&event).unwrap_or_else(|error| format!("{error}"))
+}
+
+// Formats as RFC 3339 in UTC with milliseconds, like "2024-01-31T12:34:56.789Z".
+pub(crate) fn format_timestamp(time: time::SystemTime) -> String {
+    let duration = time.duration_since(time::UNIX_EPOCH).unwrap_or_default();
+    let seconds = duration.as_secs();
+    let milliseconds = duration.subsec_millis();
+    let (year, month, day) = civil_from_days(seconds / 86_400);
+    let hour = seconds / 3_600 % 24;
+    let minute = seconds / 60 % 60;
+    let second = seconds % 60;
+
+    format!("{year:04}-{month:02}-{day:02}T{hour:02}:{minute:02}:{second:02}.{milliseconds:03}Z")
+}
+
+// Source: https://howardhinnant.github.io/date_algorithms.html#civil_from_days
+fn civil_from_days(days: u64) -> (u64, u64, u64) {
+    let days = days + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+    (year, month, day)
+}
+
+// Each macro takes a format string with arguments like `eprintln!`, optionally
+// preceded by `fields: <Fields>;`.
 #[macro_export]
 macro_rules! debug {
-    ($($argument:tt)*) => {{
-        if $crate::log::level() <= $crate::log::Level::Debug {
-            eprintln!($($argument)*);
-        }
-    }};
+    ($($argument:tt)*) => {
+        $crate::log::event!($crate::log::Level::Debug, $($argument)*)
+    };
 }
 
 #[macro_export]
 macro_rules! info {
-    ($($argument:tt)*) => {{
-        if $crate::log::level() <= $crate::log::Level::Info {
-            eprintln!($($argument)*);
-        }
-    }};
+    ($($argument:tt)*) => {
+        $crate::log::event!($crate::log::Level::Info, $($argument)*)
+    };
 }
 
 #[macro_export]
 macro_rules! warn {
-    ($($argument:tt)*) => {{
-        if $crate::log::level() <= $crate::log::Level::Warn {
-            eprintln!($($argument)*);
-        }
-    }};
+    ($($argument:tt)*) => {
+        $crate::log::event!($crate::log::Level::Warn, $($argument)*)
+    };
 }
 
+#[macro_export]
+macro_rules! error {
+    ($($argument:tt)*) => {
+        $crate::log::event!($crate::log::Level::Error, $($argument)*)
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! event {
+    ($level:expr, fields: $fields:expr; $($argument:tt)*) => {
+        $crate::log::write($level, &$fields, format_args!($($argument)*))
+    };
+    ($level:expr, $($argument:tt)*) => {
+        $crate::log::write(
+            $level,
+            &$crate::log::Fields::default(),
+            format_args!($($argument)*),
+        )
+    };
+}
+
+pub use super::error;
+pub use super::event;
 pub use super::warn;
 pub use debug;
 pub use info;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case::test_case(0, "1970-01-01T00:00:00.000Z"; "epoch")]
+    #[test_case::test_case(951_782_400_123, "2000-02-29T00:00:00.123Z"; "leap day")]
+    #[test_case::test_case(1_706_704_496_789, "2024-01-31T12:34:56.789Z"; "recent")]
+    fn format_timestamp_handles(milliseconds: u64, expected: &str) {
+        let time = time::UNIX_EPOCH + time::Duration::from_millis(milliseconds);
+        assert_eq!(format_timestamp(time), expected)
+    }
+
+    #[test]
+    fn serialize_event_handles() {
+        let fields = Fields {
+            change_kind: Some("add"),
+            service: Some("greet"),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            serialize_event(
+                time::UNIX_EPOCH,
+                Level::Info,
+                &fields,
+                format_args!("Going to {}.", "add"),
+            ),
+            r#"{"timestamp":"1970-01-01T00:00:00.000Z","level":"info","message":"Going to add.","change_kind":"add","service":"greet"}"#,
+        )
+    }
+}
@@ -1,23 +1,54 @@
-mod command;
-mod deploy;
-mod docker;
+mod check;
 mod docker_cli_plugin_metadata;
-mod docker_compose;
-mod log;
+mod forward;
+mod manifest;
+mod plan_file;
 mod provision;
 mod run_with_ssh_config;
 mod transfer_images;
 
+use anyhow::Context;
 use clap::Parser;
+use std::env;
+use std::fs;
 use std::path;
+use std::process;
+use std::time;
+use wheelsticks::command;
+use wheelsticks::deploy;
+use wheelsticks::docker;
+use wheelsticks::docker_compose;
+use wheelsticks::log;
+
+fn main() -> process::ExitCode {
+    match run() {
+        Ok(()) => process::ExitCode::SUCCESS,
+        Err(error) => {
+            log::error!(
+                fields: log::Fields {
+                    command: command::get_failed_command(&error),
+                    ..Default::default()
+                };
+                "Error: {error:?}"
+            );
+            process::ExitCode::FAILURE
+        }
+    }
+}
 
-fn main() -> anyhow::Result<()> {
+fn run() -> anyhow::Result<()> {
     let Cli {
         docker_arguments,
         dry_run,
+        log_format,
         subcommand,
     } = Cli::parse();
 
+    log::set_format(match log_format {
+        LogFormat::Text => log::Format::Text,
+        LogFormat::Json => log::Format::Json,
+    })?;
+
     log::set_level(match docker_arguments.log_level.as_deref() {
         _ if docker_arguments.debug => log::Level::Debug,
         None => log::Level::Info,
@@ -28,50 +59,21 @@ fn main() -> anyhow::Result<()> {
     })?;
 
     match subcommand {
-        Subcommand::Deploy {
-            container_engine_arguments: ContainerEngineArguments { container_engine },
+        Subcommand::Apply { plan_file } => apply(&plan_file, dry_run),
+
+        Subcommand::Check {
             docker_compose_arguments,
-            docker_compose_up_arguments:
-                DockerComposeUpArgumentsForDeploy {
-                    build,
-                    detach,
-                    force_recreate,
-                    no_build,
-                    no_start,
-                    pull,
-                    quiet_pull,
-                    remove_orphans,
-                    renew_anon_volumes,
-                    timeout,
-                    wait_timeout,
-                    wait,
-                },
-            service_names,
-        } => {
-            if detach {
-                log::warn!("Detached mode is always on, no need to set it.");
-            }
+            ignore,
+        } => check::go(check::In {
+            docker_compose_cli: docker_compose::Cli::new(
+                (&docker_arguments).into(),
+                (&docker_compose_arguments).into(),
+            ),
+            ignored_rules: ignore,
+        }),
 
-            deploy::go(deploy::In {
-                build,
-                docker_cli: docker::Cli::new(&container_engine, (&docker_arguments).into()),
-                docker_compose_cli: docker_compose::Cli::new(
-                    (&docker_arguments).into(),
-                    (&docker_compose_arguments).into(),
-                ),
-                dry_run,
-                force_recreate,
-                no_build,
-                no_start,
-                pull,
-                quiet_pull,
-                remove_orphans,
-                renew_anon_volumes,
-                service_names: service_names.into_iter().collect(),
-                timeout: timeout.map(|timeout| timeout.to_string()),
-                wait,
-                wait_timeout: wait_timeout.map(|wait_timeout| wait_timeout.to_string()),
-            })
+        Subcommand::Deploy(deploy_arguments) => {
+            deploy(&docker_arguments, dry_run, deploy_arguments, None).map(|_| ())
         }
 
         Subcommand::DockerCliPluginMetadata => {
@@ -80,6 +82,42 @@ fn main() -> anyhow::Result<()> {
             Ok(())
         }
 
+        Subcommand::Forward {
+            backends_file,
+            listen,
+        } => forward::go(forward::In {
+            backends_file,
+            listen,
+        }),
+
+        Subcommand::Plan {
+            actual: Some(actual),
+            deploy_arguments,
+            desired: Some(desired),
+            out: _,
+        } => simulate_plan(&actual, &desired, &deploy_arguments),
+
+        Subcommand::Plan {
+            deploy_arguments,
+            out,
+            ..
+        } => {
+            let plan = deploy(&docker_arguments, true, deploy_arguments, None)?;
+            if let Some(out) = out {
+                plan_file::write(
+                    &out,
+                    &plan_file::PlanFile {
+                        arguments: env::args().skip(1).collect(),
+                        plan,
+                        working_directory: env::current_dir()
+                            .context("Unable to get working directory")?,
+                    },
+                )?;
+                log::info!("Saved plan to {out:?}.");
+            }
+            Ok(())
+        }
+
         Subcommand::Provision {
             force,
             host,
@@ -125,6 +163,10 @@ struct Cli {
     #[arg(long)]
     dry_run: bool,
 
+    /// Format of log messages; "json" emits one JSON object per line
+    #[arg(long, value_enum, default_value_t = LogFormat::Text, value_name = "FORMAT")]
+    log_format: LogFormat,
+
     #[command(flatten)]
     docker_arguments: DockerArguments,
 
@@ -183,12 +225,37 @@ struct DockerArguments {
 #[allow(clippy::large_enum_variant)]
 #[derive(clap::Subcommand)]
 enum Subcommand {
+    /// Deploys exactly the changes of a saved plan
+    ///
+    /// The plan is deployed with the arguments that it was made with. This is
+    /// refused if the containers or the desired services changed since, in
+    /// which case there is nothing to do but plan again.
+    Apply {
+        /// Plan file written by `wheelsticks plan --out`
+        plan_file: path::PathBuf,
+    },
+
+    /// Checks Compose file for zero-downtime problems
+    ///
+    /// Reads the configuration like `docker compose config` and reports each
+    /// problem with the name of its rule. Fails if there are any problems,
+    /// which suits CI pipelines.
+    Check {
+        #[command(flatten)]
+        docker_compose_arguments: DockerComposeArguments,
+
+        /// Don't report problems of this rule; may be repeated
+        #[arg(long, value_name = "RULE", value_parser = check::RULES)]
+        ignore: Vec<String>,
+    },
+
     /// Create or update services
     ///
     /// Builds, (re)creates, and starts containers for a service.
     ///
-    /// Unless they are already running, this command also starts any linked
-    /// services.
+    /// This command also deploys any services that the given services depend
+    /// on, unless `--no-deps` is passed. Like the given services, they are
+    /// only changed if missing or outdated.
     ///
     /// The containers are always started in the background and left running
     /// (detached mode).
@@ -201,22 +268,60 @@ enum Subcommand {
     /// `services.*.deploy.update_config.order` in a Compose file.
     ///
     /// To force recreating all containers, use the `--force-recreate` flag.
-    Deploy {
-        #[command(flatten)]
-        container_engine_arguments: ContainerEngineArguments,
+    Deploy(DeployArguments),
 
-        #[command(flatten)]
-        docker_compose_arguments: DockerComposeArguments,
+    #[command(hide = true)]
+    DockerCliPluginMetadata,
 
-        #[command(flatten)]
-        docker_compose_up_arguments: DockerComposeUpArgumentsForDeploy,
+    /// Forwards TCP connections to backends listed in file
+    ///
+    /// Each line of the file is a backend address like "127.0.0.1:49153".
+    /// Connections are distributed round robin over available backends. The
+    /// file is read for each new connection, so replacing it switches backends
+    /// at once, while open connections are left alone. `wheelsticks deploy
+    /// --forward …` keeps such a file up to date during rollouts.
+    Forward {
+        /// Address to listen on like "127.0.0.1:8080"
+        #[arg(long)]
+        listen: String,
 
-        /// Services to consider
-        service_names: Vec<String>,
+        /// File listing backend addresses, one per line
+        backends_file: path::PathBuf,
     },
 
-    #[command(hide = true)]
-    DockerCliPluginMetadata,
+    /// Plans changes of deploy, optionally saving them
+    ///
+    /// This is like `deploy --dry-run`. With `--out`, the planned changes are
+    /// saved for `wheelsticks apply`, which deploys exactly these changes, so
+    /// they can be reviewed before any host is touched.
+    ///
+    /// With `--actual` and `--desired`, changes are planned offline from
+    /// snapshot files instead, without any container engine.
+    Plan {
+        #[command(flatten)]
+        deploy_arguments: DeployArguments,
+
+        /// Plan offline from this `docker inspect` output of containers,
+        /// printing the changes as JSON; service config hashes are calculated
+        /// natively, and all services are considered
+        #[arg(
+            conflicts_with_all = ["exclude", "no_deps", "selector", "service_names"],
+            long,
+            requires = "desired",
+            value_name = "FILE"
+        )]
+        actual: Option<path::PathBuf>,
+
+        /// Plan offline for this `docker compose config --format json`
+        /// output
+        #[arg(long, requires = "actual", value_name = "FILE")]
+        desired: Option<path::PathBuf>,
+
+        /// Save the plan to this file; not with `--build`, whose images are
+        /// only known once built
+        #[arg(conflicts_with_all = ["actual", "build"], long, value_name = "PLAN_FILE")]
+        out: Option<path::PathBuf>,
+    },
 
     /// Provisions host with container engine
     Provision {
@@ -264,6 +369,122 @@ enum Subcommand {
     },
 }
 
+#[derive(clap::Args)]
+struct DeployArguments {
+    #[command(flatten)]
+    container_engine_arguments: ContainerEngineArguments,
+
+    /// Context of a host to deploy to; if repeated, hosts are deployed one
+    /// after another, stopping at the first failure
+    #[arg(long)]
+    context: Vec<String>,
+
+    /// File listing contexts of hosts to deploy to, one per line
+    #[arg(long)]
+    context_file: Option<path::PathBuf>,
+
+    #[command(flatten)]
+    docker_compose_arguments: DockerComposeArguments,
+
+    /// Talk to the Docker Engine API directly instead of the container
+    /// engine CLI where possible, for "unix://…" and "ssh://…" endpoints
+    #[arg(long)]
+    engine_api: bool,
+
+    /// Stream lifecycle events as NDJSON to this file, FIFO, or
+    /// "http://…" endpoint
+    #[arg(long, value_name = "TARGET")]
+    events: Option<String>,
+
+    /// Skip services matching this glob pattern; may be repeated
+    #[arg(long, value_name = "PATTERN")]
+    exclude: Vec<String>,
+
+    /// Keep FILE listing host addresses of SERVICE containers publishing
+    /// PORT, for `wheelsticks forward`; may be repeated
+    #[arg(long, value_name = "SERVICE:PORT=FILE")]
+    forward: Vec<String>,
+
+    /// JSON file listing Compose projects to deploy in order, instead of a
+    /// single project
+    #[arg(
+        conflicts_with_all = [
+            "env_file",
+            "file",
+            "profile",
+            "project_directory",
+            "project_name",
+            "service_names",
+        ],
+        long,
+    )]
+    manifest: Option<path::PathBuf>,
+
+    /// Write deployment metrics to this file in the Prometheus text format,
+    /// like for the textfile collector of the node exporter
+    #[arg(long, value_name = "PATH")]
+    metrics_file: Option<path::PathBuf>,
+
+    /// Percentage of probe requests that may fail before the deployment
+    /// counts as failed
+    #[arg(
+        default_value_t = 0.0,
+        long,
+        requires = "probe_url",
+        value_name = "PERCENT"
+    )]
+    probe_error_budget: f64,
+
+    /// Request this "http://…" URL throughout the deployment and report the
+    /// observed availability
+    #[arg(long, value_name = "URL")]
+    probe_url: Option<String>,
+
+    /// Only consider services with this Compose label, optionally of this
+    /// value; if repeated, all must match
+    #[arg(long, value_name = "LABEL[=VALUE]")]
+    selector: Vec<String>,
+
+    /// Fail instead of updating a start-first service stop-first if only
+    /// one container can have its settings, like a fixed host port
+    #[arg(long)]
+    strict_start_first: bool,
+
+    /// Switch DNS aliases of services from old to new containers once
+    /// ready, so that clients never resolve a starting or stopping one
+    #[arg(long)]
+    switch_aliases: bool,
+
+    #[command(flatten)]
+    docker_compose_up_arguments: DockerComposeUpArgumentsForDeploy,
+
+    #[command(flatten)]
+    retry_arguments: RetryArguments,
+
+    /// How to calculate service config hashes, which decide whether a
+    /// service is updated
+    #[arg(default_value = "compose", long, value_enum)]
+    config_hash: ConfigHash,
+
+    /// Services to consider; glob patterns like "api-*" are allowed
+    service_names: Vec<String>,
+}
+
+#[derive(Clone, clap::ValueEnum)]
+enum LogFormat {
+    Text,
+    Json,
+}
+
+#[derive(Clone, clap::ValueEnum)]
+enum ConfigHash {
+    /// Ask Docker Compose via `docker compose config --hash '*'`
+    Compose,
+    /// Calculate like Docker Compose but without it, for other Compose
+    /// implementations
+    Native,
+}
+
 #[derive(clap::Args)]
 struct ContainerEngineArguments {
     /// Container engine program to use
@@ -322,7 +543,7 @@ struct DockerComposeArguments {
 // TODO: Update arguments based on above source.
 #[derive(clap::Args)]
 struct DockerComposeUpArgumentsForDeploy {
-    /// Build images before starting containers
+    /// Build images of services before planning
     #[arg(long)]
     build: bool,
 
@@ -338,6 +559,10 @@ struct DockerComposeUpArgumentsForDeploy {
     #[arg(long)]
     no_build: bool,
 
+    /// Don't start linked services
+    #[arg(long)]
+    no_deps: bool,
+
     /// Don't start the services after creating them
     #[arg(long)]
     no_start: bool,
@@ -373,6 +598,26 @@ struct DockerComposeUpArgumentsForDeploy {
     wait_timeout: Option<i64>,
 }
 
+#[derive(clap::Args)]
+struct RetryArguments {
+    /// Maximum number of attempts for each container engine command
+    #[arg(default_value_t = 1, long, value_parser = clap::value_parser!(u16).range(1..))]
+    retry_attempts: u16,
+
+    /// Delay in seconds before first retry, doubled after each further attempt
+    #[arg(default_value_t = 1, long)]
+    retry_backoff: u64,
+
+    /// Exit code of a transient failure to retry; if neither this nor
+    /// `--retry-stderr` is given, any failure is retried
+    #[arg(long)]
+    retry_exit_code: Vec<i32>,
+
+    /// Text in stderr of a transient failure to retry
+    #[arg(long)]
+    retry_stderr: Vec<String>,
+}
+
 impl<'a> From<&'a DockerArguments> for docker::Arguments<'a> {
     fn from(
         DockerArguments {
@@ -431,6 +676,287 @@ impl<'a> From<&'a DockerComposeArguments> for docker_compose::Arguments<'a> {
     }
 }
 
+fn apply(plan_file: &path::Path, dry_run: bool) -> anyhow::Result<()> {
+    let plan_file::PlanFile {
+        arguments,
+        plan,
+        working_directory,
+    } = plan_file::read(plan_file)?;
+
+    let current_directory = env::current_dir().context("Unable to get working directory")?;
+    if current_directory != working_directory {
+        anyhow::bail!(
+            "Plan was saved in {working_directory:?}, so apply it there, \
+            not in {current_directory:?}"
+        );
+    }
+
+    let Cli {
+        docker_arguments,
+        subcommand,
+        ..
+    } = Cli::try_parse_from([env!("CARGO_BIN_NAME").into()].into_iter().chain(arguments))
+        .context("Unable to parse arguments of plan")?;
+    let Subcommand::Plan {
+        deploy_arguments, ..
+    } = subcommand
+    else {
+        anyhow::bail!("Arguments of plan are not for `wheelsticks plan`");
+    };
+
+    deploy(&docker_arguments, dry_run, deploy_arguments, Some(plan)).map(|_| ())
+}
+
+fn simulate_plan(
+    actual: &path::Path,
+    desired: &path::Path,
+    DeployArguments {
+        docker_compose_arguments: DockerComposeArguments { profile, .. },
+        docker_compose_up_arguments:
+            DockerComposeUpArgumentsForDeploy {
+                force_recreate,
+                remove_orphans,
+                ..
+            },
+        strict_start_first,
+        ..
+    }: &DeployArguments,
+) -> anyhow::Result<()> {
+    let actual_containers = fs::read_to_string(actual)
+        .with_context(|| format!("Unable to read actual containers {actual:?}"))?;
+    let compose_config = fs::read_to_string(desired)
+        .with_context(|| format!("Unable to read desired state {desired:?}"))?;
+
+    let changes = deploy::simulate_plan::go(deploy::simulate_plan::In {
+        actual_containers: &actual_containers,
+        compose_config: &compose_config,
+        force_recreate: *force_recreate,
+        profiles: profile,
+        remove_orphans: *remove_orphans,
+        strict_start_first: *strict_start_first,
+    })?;
+    println!("{}", serde_json::to_string_pretty(&changes)?);
+    Ok(())
+}
+
+fn deploy(
+    docker_arguments: &DockerArguments,
+    dry_run: bool,
+    DeployArguments {
+        config_hash,
+        container_engine_arguments: ContainerEngineArguments { container_engine },
+        context,
+        context_file,
+        docker_compose_arguments,
+        engine_api,
+        events,
+        exclude,
+        forward,
+        manifest,
+        metrics_file,
+        probe_error_budget,
+        probe_url,
+        selector,
+        strict_start_first,
+        switch_aliases,
+        docker_compose_up_arguments:
+            DockerComposeUpArgumentsForDeploy {
+                build,
+                detach,
+                force_recreate,
+                no_build,
+                no_deps,
+                no_start,
+                pull,
+                quiet_pull,
+                remove_orphans,
+                renew_anon_volumes,
+                timeout,
+                wait_timeout,
+                wait,
+            },
+        retry_arguments:
+            RetryArguments {
+                retry_attempts,
+                retry_backoff,
+                retry_exit_code,
+                retry_stderr,
+            },
+        service_names,
+    }: DeployArguments,
+    saved_plan: Option<deploy::SavedPlan>,
+) -> anyhow::Result<deploy::SavedPlan> {
+    if detach {
+        log::warn!("Detached mode is always on, no need to set it.");
+    }
+
+    let contexts = get_contexts(context, context_file)?;
+    let manifest = manifest
+        .map(|manifest| manifest::read(&manifest))
+        .transpose()?;
+    let mut events = match events {
+        None => deploy::events::Stream::default(),
+        Some(target) => deploy::events::Stream::open(&target)?,
+    };
+    // A dry run deploys nothing, so it must not count as a success.
+    if let Some(metrics_file) = metrics_file.filter(|_| !dry_run) {
+        events = events.with_metrics_file(metrics_file);
+    }
+
+    Ok(deploy::go(deploy::In {
+        availability_probe: probe_url.map(|url| deploy::AvailabilityProbe {
+            error_budget: probe_error_budget,
+            url,
+        }),
+        build,
+        config_hash_source: match config_hash {
+            ConfigHash::Compose => deploy::ConfigHashSource::Compose,
+            ConfigHash::Native => deploy::ConfigHashSource::Native,
+        },
+        dry_run,
+        engine_api,
+        events,
+        force_recreate,
+        forwards: forward
+            .iter()
+            .map(|forward| parse_forward(forward))
+            .collect::<anyhow::Result<_>>()?,
+        hosts: if contexts.is_empty() {
+            vec![None]
+        } else {
+            contexts
+                .iter()
+                .map(|context| Some(context.as_str()))
+                .collect()
+        }
+        .into_iter()
+        .map(|context| deploy::Host {
+            context,
+            docker_cli: docker::Cli::new(
+                &container_engine,
+                docker_arguments_for_context(docker_arguments, context),
+            ),
+            projects: match &manifest {
+                None => vec![deploy::Project {
+                    backend: None,
+                    docker_compose_cli: docker_compose::Cli::new(
+                        docker_arguments_for_context(docker_arguments, context),
+                        (&docker_compose_arguments).into(),
+                    ),
+                    name: None,
+                }],
+                Some(manifest) => manifest
+                    .projects
+                    .iter()
+                    .map(|project| deploy::Project {
+                        backend: None,
+                        docker_compose_cli: docker_compose::Cli::new(
+                            docker_arguments_for_context(docker_arguments, context),
+                            docker_compose_arguments_for_project(
+                                &docker_compose_arguments,
+                                project,
+                            ),
+                        ),
+                        name: Some(&project.name),
+                    })
+                    .collect(),
+            },
+        })
+        .collect(),
+        no_build,
+        no_deps,
+        no_start,
+        pull,
+        quiet_pull,
+        remove_orphans,
+        renew_anon_volumes,
+        retry_policy: command::RetryPolicy {
+            attempts: retry_attempts,
+            backoff: time::Duration::from_secs(retry_backoff),
+            exit_codes: retry_exit_code,
+            stderr_patterns: retry_stderr,
+        },
+        saved_plan,
+        service_selection: deploy::ServiceSelection {
+            exclude_patterns: exclude,
+            label_selectors: selector,
+            name_patterns: service_names,
+        },
+        strict_start_first,
+        switch_aliases,
+        timeout: timeout.map(|timeout| timeout.to_string()),
+        wait,
+        wait_timeout: wait_timeout.map(|wait_timeout| wait_timeout.to_string()),
+    })?)
+}
+
+// Parses "<service>:<port>=<file>".
+fn parse_forward(forward: &str) -> anyhow::Result<deploy::Forward> {
+    let parse = || {
+        let (target, backends_file) = forward.split_once('=')?;
+        let (service_name, container_port) = target.rsplit_once(':')?;
+        Some(deploy::Forward {
+            backends_file: backends_file.into(),
+            container_port: container_port.parse().ok()?,
+            service_name: service_name.into(),
+        })
+    };
+    parse().with_context(|| format!("Invalid forward {forward:?}, expected SERVICE:PORT=FILE"))
+}
+
+fn get_contexts(
+    mut contexts: Vec<String>,
+    context_file: Option<path::PathBuf>,
+) -> anyhow::Result<Vec<String>> {
+    if let Some(context_file) = context_file {
+        let file_contexts = fs::read_to_string(&context_file)
+            .with_context(|| format!("Unable to read context file {context_file:?}"))?;
+        contexts.extend(
+            file_contexts
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                .map(|context| context.into()),
+        );
+    }
+    Ok(contexts)
+}
+
+fn docker_arguments_for_context<'a>(
+    docker_arguments: &'a DockerArguments,
+    context: Option<&'a str>,
+) -> docker::Arguments<'a> {
+    match context {
+        None => docker_arguments.into(),
+        Some(context) => docker::Arguments {
+            context: Some(context),
+            host: None,
+            ..docker_arguments.into()
+        },
+    }
+}
+
+fn docker_compose_arguments_for_project<'a>(
+    docker_compose_arguments: &'a DockerComposeArguments,
+    manifest::Project {
+        env_file,
+        file,
+        name: _,
+        profile,
+        project_directory,
+        project_name,
+    }: &'a manifest::Project,
+) -> docker_compose::Arguments<'a> {
+    docker_compose::Arguments {
+        env_file,
+        file,
+        profile,
+        project_directory: project_directory.as_deref(),
+        project_name: project_name.as_deref(),
+        ..docker_compose_arguments.into()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -449,12 +975,43 @@ mod tests {
         assert!(get_readme().starts_with(&top_level_heading));
     }
 
+    #[test_case::test_case(&["plan", "--out", "plan.json"], true; "out")]
+    #[test_case::test_case(&["plan", "--build", "--out", "plan.json"], false; "out with build")]
+    #[test_case::test_case(
+        &["plan", "--actual", "a.json", "--desired", "d.json"], true; "simulation"
+    )]
+    #[test_case::test_case(
+        &["plan", "--actual", "a.json", "--desired", "d.json", "api"], false;
+        "simulation with service"
+    )]
+    #[test_case::test_case(
+        &["plan", "--actual", "a.json", "--desired", "d.json", "--exclude", "api"], false;
+        "simulation with exclude"
+    )]
+    #[test_case::test_case(
+        &["plan", "--actual", "a.json", "--desired", "d.json", "--no-deps"], false;
+        "simulation without dependencies"
+    )]
+    #[test_case::test_case(
+        &["plan", "--actual", "a.json", "--desired", "d.json", "--selector", "tier"], false;
+        "simulation with selector"
+    )]
+    fn plan_arguments_handle(arguments: &[&str], is_valid: bool) {
+        let arguments = [&[env!("CARGO_BIN_NAME")], arguments].concat();
+
+        assert_eq!(Cli::try_parse_from(arguments).is_ok(), is_valid)
+    }
+
     fn get_readme() -> &'static str {
         include_str!("../README.md")
     }
 
     #[test_case::test_case(&[]; "")]
+    #[test_case::test_case(&["apply"]; "apply")]
+    #[test_case::test_case(&["check"]; "check")]
     #[test_case::test_case(&["deploy"]; "deploy")]
+    #[test_case::test_case(&["forward"]; "forward")]
+    #[test_case::test_case(&["plan"]; "plan")]
     #[test_case::test_case(&["provision"]; "provision")]
     #[test_case::test_case(&["run-with-ssh-config"]; "run-with-ssh-config")]
     #[test_case::test_case(&["transfer-images"]; "transfer-images")]
@@ -0,0 +1,95 @@
+use anyhow::Context;
+use std::fs;
+use std::path;
+
+pub fn read(path: &path::Path) -> anyhow::Result<Manifest> {
+    let manifest =
+        fs::read_to_string(path).with_context(|| format!("Unable to read manifest {path:?}"))?;
+    let manifest = serde_json::from_str::<Manifest>(&manifest)
+        .with_context(|| format!("Unable to parse manifest {path:?}"))?;
+    let base_folder = path.parent().unwrap_or(path::Path::new(""));
+
+    Ok(Manifest {
+        projects: manifest
+            .projects
+            .into_iter()
+            .map(|project| resolve_paths(project, base_folder))
+            .collect(),
+    })
+}
+
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest {
+    pub projects: Vec<Project>,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Project {
+    pub name: String,
+    #[serde(default)]
+    pub env_file: Vec<String>,
+    #[serde(default)]
+    pub file: Vec<String>,
+    #[serde(default)]
+    pub profile: Vec<String>,
+    pub project_directory: Option<String>,
+    pub project_name: Option<String>,
+}
+
+// Relative paths are relative to the manifest, not the working directory.
+fn resolve_paths(project: Project, base_folder: &path::Path) -> Project {
+    let resolve = |path: String| base_folder.join(path).to_string_lossy().into_owned();
+
+    Project {
+        env_file: project.env_file.into_iter().map(resolve).collect(),
+        file: project.file.into_iter().map(resolve).collect(),
+        project_directory: project.project_directory.map(resolve),
+        ..project
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn handles() -> anyhow::Result<()> {
+        let folder = tempfile::tempdir()?;
+        let path = folder.path().join("manifest.json");
+        fs::File::create(&path)?.write_all(
+            br#"{
+  "projects": [
+    {"name": "infra", "file": ["infra/compose.yaml", "/etc/compose.yaml"]},
+    {"name": "apps", "env_file": [".env"], "profile": ["web"], "project_directory": "apps"}
+  ]
+}"#,
+        )?;
+
+        let manifest = read(&path)?;
+
+        let infra = &manifest.projects[0];
+        assert_eq!(infra.name, "infra");
+        assert_eq!(
+            infra.file,
+            [
+                folder.path().join("infra/compose.yaml").to_string_lossy(),
+                "/etc/compose.yaml".into(),
+            ],
+        );
+        let apps = &manifest.projects[1];
+        assert_eq!(apps.name, "apps");
+        assert_eq!(
+            apps.env_file,
+            [folder.path().join(".env").to_string_lossy()]
+        );
+        assert_eq!(apps.profile, ["web"]);
+        assert_eq!(
+            apps.project_directory,
+            Some(folder.path().join("apps").to_string_lossy().into_owned()),
+        );
+        Ok(())
+    }
+}
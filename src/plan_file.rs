@@ -0,0 +1,25 @@
+use anyhow::Context;
+use std::fs;
+use std::path;
+use wheelsticks::deploy;
+
+pub fn read(path: &path::Path) -> anyhow::Result<PlanFile> {
+    let plan_file =
+        fs::read_to_string(path).with_context(|| format!("Unable to read plan file {path:?}"))?;
+    serde_json::from_str(&plan_file).with_context(|| format!("Unable to parse plan file {path:?}"))
+}
+
+pub fn write(path: &path::Path, plan_file: &PlanFile) -> anyhow::Result<()> {
+    let plan_file = serde_json::to_string_pretty(plan_file)?;
+    fs::write(path, plan_file + "\n").with_context(|| format!("Unable to write plan file {path:?}"))
+}
+
+// Besides the changes, the plan file keeps the arguments of `wheelsticks plan`
+// so that `wheelsticks apply` deploys the same projects to the same hosts.
+#[derive(serde::Deserialize, serde::Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct PlanFile {
+    pub arguments: Vec<String>,
+    pub plan: deploy::SavedPlan,
+    pub working_directory: path::PathBuf,
+}
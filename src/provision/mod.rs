@@ -39,7 +39,7 @@ pub fn go(
                 command
             }
             Host::Vagrant { vm } => {
-                command::status_ok(vagrant().arg("up").args(vm))?;
+                command::status_ok(vagrant().arg("up").args(vm), command::Stderr::Inherit)?;
                 let mut command = vagrant();
                 command.args(["ssh", "--command", "bash"]).args(vm).args(
                     ssh_config
@@ -20,7 +20,10 @@ pub fn go(
         .canonicalize()
         .with_context(|| format!("Unable to make {ssh_config:?} absolute"))?;
 
-    let real_ssh = command::stdout_utf8(process::Command::new("which").arg("ssh"))?;
+    let real_ssh = command::stdout_utf8(
+        process::Command::new("which").arg("ssh"),
+        command::Stderr::Inherit,
+    )?;
     let real_ssh = real_ssh.trim_end();
 
     let custom_bin = tempfile::tempdir_in(".")?;
@@ -47,7 +50,7 @@ pub fn go(
         log::info!("Would run: {command_in_context:?}");
         Ok(())
     } else {
-        command::status_ok(&mut command_in_context)
+        command::status_ok(&mut command_in_context, command::Stderr::Inherit)
     }
 }
 
@@ -23,6 +23,13 @@ test_container_engine() {
 
   sleep 2s
 
+  # Fails if native service config hashes differ from those of Docker Compose,
+  # as the deployment's verification compares them with container labels. The
+  # example has dependencies and extension fields, which Compose does not hash.
+  GREET_VERSION=B wheelsticks_deploy --config-hash native
+
+  sleep 2s
+
   kill %%
 
   for greet_version in 'A' 'B'; do
@@ -39,12 +46,36 @@ test_container_engine() {
   fi
 }
 
+# Compares native service config hashes with golden ones of Docker Compose. The
+# example covers ports, environment variables, volumes, and dependencies.
+test_native_config_hashes() {
+  cd example
+
+  local -r expected_hashes="$(docker compose config --hash '*' | sort)"
+  local -r native_hashes="$(
+    "${WHEELSTICKS}" plan --actual <(echo '[]') \
+      --desired <(docker compose config --format json) |
+      awk -F '"' '/"service_config_hash"/ { hash = $4 } /"service_name"/ { print $4, hash }' |
+      sort
+  )"
+
+  if [[ "${native_hashes}" != "${expected_hashes}" ]]; then
+    printf 'Native config hashes:\n%s\nDocker Compose config hashes:\n%s\n' \
+      "${native_hashes}" "${expected_hashes}" >&2
+    exit 1
+  fi
+}
+
 wheelsticks_deploy() {
   "${WHEELSTICKS}" deploy --container-engine "${WHEELSTICKS_CONTAINER_ENGINE}" \
-    --wait --wait-timeout 30
+    --wait --wait-timeout 30 "$@"
 }
 
 main() {
+  (
+    test_native_config_hashes
+  )
+
   (
     WHEELSTICKS_CONTAINER_ENGINE=docker test_container_engine
   )